@@ -1,66 +1,743 @@
-import WebSocket, { WebSocketServer } from 'ws';
+import { createHash, randomUUID } from 'crypto';
+import http from 'http';
+import WebSocket, { RawData, WebSocketServer } from 'ws';
 
 const PORT = process.env.PORT ? parseInt(process.env.PORT) : 8080;
+// Must match `PROTOCOL_VERSION` in YewChat/protocol.
+const PROTOCOL_VERSION = 14;
+// Must match `MIN_NICK_LEN`, `MAX_NICK_LEN` and `RESERVED_NICKS` in YewChat/protocol.
+const MIN_NICK_LEN = 2;
+const MAX_NICK_LEN = 24;
+const RESERVED_NICKS = ['system', 'server', 'admin', 'administrator', 'moderator', 'root', 'everyone', 'here', 'yewchat'];
+// Free nicks offered when the one asked for is taken.
+const NICK_SUGGESTIONS = 3;
+// Bounds on the `session` token sent with `register`; short ones would be guessable.
+const MIN_SESSION_LEN = 16;
+const MAX_SESSION_LEN = 128;
+// Must match `DEFAULT_ROOM` / `MAX_ROOM_LEN` in YewChat/protocol.
+const DEFAULT_ROOM = 'global';
+const MAX_ROOM_LEN = 32;
+// Must match `MAX_REACTION_LEN` and `MAX_MESSAGE_LEN` in YewChat/protocol.
+const MAX_REACTION_LEN = 16;
+const MAX_MESSAGE_LEN = 4000;
+// Messages kept per room or DM pair, and the most one `history` page may hold.
+const HISTORY_KEPT = 1000;
+const MAX_HISTORY_PAGE = 200;
+// Must match `MAX_UPLOAD_BYTES`, `UPLOAD_CHUNK_BYTES`, `MAX_FILE_NAME_LEN` and
+// `FILES_PATH` in YewChat/protocol.
+const MAX_UPLOAD_BYTES = 8 * 1024 * 1024;
+const UPLOAD_CHUNK_BYTES = 64 * 1024;
+const MAX_FILE_NAME_LEN = 255;
+const FILES_PATH = '/files/';
+// Uploaded files are kept in memory; the oldest go once they add up to more than this.
+const FILES_KEPT_BYTES = 256 * 1024 * 1024;
+// Client ids remembered to spot resent messages; the oldest are forgotten first.
+const CLIENT_IDS_KEPT = 10_000;
+// Nicks whose last holder we remember, so DMs with someone offline stay theirs.
+const NICK_AUTHORS_KEPT = 10_000;
+
 interface User {
     ws: WebSocket;
-    nick: String;
+    nick: string;
+    // Derived from the session token; marks their messages whatever nick they go by.
+    author: string;
     isAlive: boolean;
+    rooms: Set<string>;
+    // Announced uploads still receiving chunks, by the client's upload id.
+    uploads: Map<number, PendingUpload>;
+}
+
+interface PendingUpload {
+    channel: Channel;
+    name: string;
+    mime: string;
+    size: number;
+    parts: Buffer[];
+    received: number;
+}
+
+interface Attachment {
+    id: string;
+    name: string;
+    mime: string;
+    size: number;
 }
 
-interface Message {
-    messageType: String;
-    data: String;
-    dataArray: String[];
+interface StoredFile {
+    name: string;
+    mime: string;
+    data: Buffer;
+}
+
+type Channel = { room: string } | { direct: string };
+
+// Frames mirror `ClientFrame` / `ServerFrame` in YewChat/protocol/src/lib.rs.
+type ClientFrame =
+    | { type: 'register'; nick: string; version: number; session: string }
+    | { type: 'join'; room: string }
+    | { type: 'leave'; room: string }
+    | { type: 'message'; channel: Channel; text: string; replyTo?: number; mentions?: string[]; emote?: boolean; clientId?: string }
+    | { type: 'history'; channel: Channel; before?: number; limit: number }
+    | { type: 'typing'; channel: Channel; typing: boolean }
+    | { type: 'edit'; channel: Channel; id: number; text: string; mentions?: string[] }
+    | { type: 'delete'; channel: Channel; id: number }
+    | { type: 'react'; channel: Channel; id: number; emoji: string }
+    | { type: 'unreact'; channel: Channel; id: number; emoji: string }
+    | { type: 'upload'; channel: Channel; upload: number; name: string; mime: string; size: number };
+
+type ErrorCode =
+    | 'unsupportedVersion'
+    | 'notRegistered'
+    | 'invalidRoom'
+    | 'notInRoom'
+    | 'unknownUser'
+    | 'unknownMessage'
+    | 'notAuthor'
+    | 'tooLarge'
+    | 'unknownUpload'
+    | 'badFrame';
+
+interface ChatMessage {
+    id: number;
+    channel: Channel;
+    from: string;
+    author?: string;
+    text: string;
+    time: number;
+    replyTo?: number;
+    edited?: number;
+    deleted?: boolean;
+    reactions?: Record<string, string[]>;
+    mentions?: string[];
+    emote?: boolean;
+    attachment?: Attachment;
+    // Only on the copy echoed back to the author.
+    clientId?: string;
 }
 
+type RegisterStatus = 'accepted' | 'taken' | 'invalid';
+
+type ServerFrame =
+    | { type: 'registerResult'; status: RegisterStatus; nick: string; author?: string; message?: string; suggestions?: string[] }
+    | { type: 'users'; users: string[] }
+    | { type: 'members'; room: string; users: string[] }
+    | { type: 'rooms'; rooms: string[] }
+    | ({ type: 'message' } & ChatMessage)
+    | ({ type: 'update' } & ChatMessage)
+    | { type: 'history'; channel: Channel; messages: ChatMessage[]; complete: boolean }
+    | { type: 'typing'; channel: Channel; from: string; typing: boolean }
+    | { type: 'uploadProgress'; upload: number; received: number }
+    | { type: 'uploadFailed'; upload: number; code: ErrorCode; message: string }
+    | { type: 'error'; code: ErrorCode; message: string; clientId?: string };
+
 let users: User[] = [];
+// Keyed by `historyKey`, oldest first.
+const history = new Map<string, ChatMessage[]>();
+// Seeded from the clock so ids keep increasing across restarts and never clash
+// with ids clients still hold from an earlier run.
+let nextId = Date.now();
+// Finished uploads by their random id, oldest first.
+const files = new Map<string, StoredFile>();
+// Where each author's `clientId`s were stored, keyed by `${author}\n${clientId}`, oldest first.
+// Keyed by author so a resend after a rename is still recognised.
+const clientIds = new Map<string, { channel: Channel; id: number }>();
+// The author who last registered each nick, oldest first.
+const nickAuthors = new Map<string, string>();
+
+// Files are downloaded over plain HTTP from the same port the websocket listens on.
+const server = http.createServer((req, res) => {
+    const url = req.url ?? '';
+    const file = req.method === 'GET' && url.startsWith(FILES_PATH) ? files.get(url.slice(FILES_PATH.length)) : undefined;
+    if (!file) {
+        res.writeHead(404).end();
+        return;
+    }
+    // Only media is shown in the browser; everything else downloads. The sandbox keeps
+    // uploaded HTML or SVG from running scripts on this origin.
+    const inline = /^(image|video|audio)\//.test(file.mime);
+    res.writeHead(200, {
+        'Content-Type': file.mime || 'application/octet-stream',
+        'Content-Length': file.data.length,
+        'Content-Disposition': `${inline ? 'inline' : 'attachment'}; filename*=UTF-8''${encodeURIComponent(file.name)}`,
+        'Content-Security-Policy': 'sandbox',
+        'X-Content-Type-Options': 'nosniff',
+        'Cache-Control': 'private, max-age=31536000, immutable',
+    });
+    res.end(file.data);
+});
 
 console.log(`Listening on port ${PORT}`);
-const wss = new WebSocketServer({ port: PORT });
+const wss = new WebSocketServer({ server });
+server.listen(PORT);
 
 wss.on('connection', (ws: WebSocket) => {
     console.log('ws connected');
 
-    ws.on('message', (data) => {
+    ws.on('message', (data: RawData, isBinary: boolean) => {
+        if (isBinary) {
+            const sender = users.find((u) => u.ws === ws);
+            if (sender) {
+                receiveChunk(sender, Buffer.isBuffer(data) ? data : Buffer.concat(Array.isArray(data) ? data : [Buffer.from(data)]));
+            } else {
+                send(ws, { type: 'error', code: 'notRegistered', message: 'register first' });
+            }
+            return;
+        }
         const raw_data = data.toString();
         try {
-            const parsed_data: Message = JSON.parse(raw_data);
-            switch (parsed_data.messageType) {
-                case 'register':
-                    users.push({ ws, nick: parsed_data.data, isAlive: true });
-                    broadcast(JSON.stringify({ messageType: 'users', dataArray: users.map((u) => u.nick) }));
+            const parsed_data: ClientFrame = JSON.parse(raw_data);
+            if (parsed_data.type === 'register') {
+                register(ws, parsed_data.nick, parsed_data.version, parsed_data.session);
+                return;
+            }
+            const sender = users.find((u) => u.ws === ws);
+            if (!sender) {
+                send(ws, { type: 'error', code: 'notRegistered', message: 'register first' });
+                return;
+            }
+            switch (parsed_data.type) {
+                case 'join':
+                    join(sender, parsed_data.room);
+                    break;
+                case 'leave':
+                    leave(sender, parsed_data.room);
                     break;
                 case 'message':
-                    const sender = users.find((u) => u.ws === ws);
-                    if (sender) {
-                        broadcast(
-                            JSON.stringify({
-                                messageType: 'message',
-                                data: JSON.stringify({
-                                    from: sender.nick,
-                                    message: parsed_data.data,
-                                    time: Date.now(),
-                                }),
-                            })
-                        );
+                    message(sender, parsed_data);
+                    break;
+                case 'history':
+                    sendHistory(sender, parsed_data.channel, parsed_data.before, parsed_data.limit);
+                    break;
+                case 'typing':
+                    typing(sender, parsed_data.channel, parsed_data.typing);
+                    break;
+                case 'edit':
+                    if (!textFits(sender, parsed_data.text)) {
+                        break;
                     }
+                    update(sender, parsed_data.channel, parsed_data.id, (m) => ({
+                        ...m,
+                        text: parsed_data.text,
+                        mentions: checkMentions(parsed_data.text, parsed_data.mentions),
+                    }));
+                    break;
+                case 'delete':
+                    update(sender, parsed_data.channel, parsed_data.id, (m) => {
+                        if (m.attachment) {
+                            files.delete(m.attachment.id);
+                        }
+                        return { ...m, text: '', deleted: true, reactions: undefined, mentions: undefined, attachment: undefined };
+                    });
+                    break;
+                case 'react':
+                case 'unreact':
+                    react(sender, parsed_data.channel, parsed_data.id, parsed_data.emoji, parsed_data.type === 'react');
+                    break;
+                case 'upload':
+                    startUpload(sender, parsed_data);
+                    break;
+                default:
+                    send(ws, { type: 'error', code: 'badFrame', message: 'unknown frame type' });
             }
         } catch (e) {
             console.log('Error in message', e);
+            send(ws, { type: 'error', code: 'badFrame', message: 'frame is not valid JSON' });
         }
     });
+
+    // Frees the nick right away, so logging back in or reloading can take it again.
+    ws.on('close', () => {
+        console.log('ws disconnected');
+        removeUsers(users.filter((u) => u.ws === ws));
+    });
 });
 
+const register = (ws: WebSocket, nick: string, version: number, session: string) => {
+    if (version !== PROTOCOL_VERSION) {
+        send(ws, {
+            type: 'error',
+            code: 'unsupportedVersion',
+            message: `expected protocol version ${PROTOCOL_VERSION}, got ${version}`,
+        });
+        return;
+    }
+    if (typeof session !== 'string' || session.length < MIN_SESSION_LEN || session.length > MAX_SESSION_LEN) {
+        send(ws, { type: 'error', code: 'badFrame', message: 'register needs a session token' });
+        return;
+    }
+    const wanted = String(nick ?? '').trim();
+    const problem = nickProblem(wanted);
+    if (problem) {
+        send(ws, { type: 'registerResult', status: 'invalid', nick: wanted, message: problem });
+        return;
+    }
+    const current = users.find((u) => u.ws === ws);
+    if (nickHolder(wanted, current)) {
+        send(ws, {
+            type: 'registerResult',
+            status: 'taken',
+            nick: wanted,
+            message: `${wanted} is already taken`,
+            suggestions: suggestNicks(wanted, current),
+        });
+        return;
+    }
+    if (current) {
+        // A rename, or under the same nick a request to hear the users and rooms again.
+        // The connection keeps the author it registered with first.
+        current.nick = wanted;
+        rememberNick(current);
+        send(ws, { type: 'registerResult', status: 'accepted', nick: wanted, author: current.author });
+        broadcastUsers();
+        current.rooms.forEach((room) => broadcastMembers(room));
+        send(ws, { type: 'rooms', rooms: roomNames() });
+        return;
+    }
+    const author = createHash('sha256').update(session).digest('hex').slice(0, 16);
+    const user: User = { ws, nick: wanted, author, isAlive: true, rooms: new Set(), uploads: new Map() };
+    users.push(user);
+    rememberNick(user);
+    send(ws, { type: 'registerResult', status: 'accepted', nick: wanted, author });
+    broadcastUsers();
+    join(user, DEFAULT_ROOM);
+};
+
+const rememberNick = (user: User) => {
+    nickAuthors.delete(user.nick);
+    nickAuthors.set(user.nick, user.author);
+    if (nickAuthors.size > NICK_AUTHORS_KEPT) {
+        nickAuthors.delete(nickAuthors.keys().next().value as string);
+    }
+};
+
+// Who `nick` stands for: whoever holds it now, or else whoever held it last.
+const authorOf = (nick: string): string | undefined =>
+    users.find((u) => u.nick === nick)?.author ?? nickAuthors.get(nick);
+
+// Same rules as `validate_nick` in YewChat/protocol, for an already trimmed nick.
+const nickProblem = (nick: string): string | null => {
+    const bad = [...nick].find((c) => !/^[\p{Alphabetic}\p{N}_.-]$/u.test(c));
+    if (nick.length === 0) {
+        return 'Enter a nick';
+    }
+    if (bad !== undefined) {
+        return /\s/u.test(bad) ? "Nicks can't contain spaces" : `"${bad}" isn't allowed; use letters, digits, _, - and .`;
+    }
+    const length = [...nick].length;
+    if (length < MIN_NICK_LEN) {
+        return `Nicks are at least ${MIN_NICK_LEN} characters`;
+    }
+    if (length > MAX_NICK_LEN) {
+        return `Nicks are at most ${MAX_NICK_LEN} characters`;
+    }
+    if (!/^[\p{Alphabetic}\p{N}]/u.test(nick)) {
+        return 'Nicks start with a letter or digit';
+    }
+    if (RESERVED_NICKS.includes(nick.toLowerCase())) {
+        return 'That nick is reserved';
+    }
+    return null;
+};
+
+// Whoever else is connected as `nick`. Nicks differing only in case count as the
+// same, so nobody can pass for someone else.
+const nickHolder = (nick: string, except?: User): User | undefined =>
+    users.find((u) => u !== except && u.nick.toLowerCase() === nick.toLowerCase());
+
+const suggestNicks = (nick: string, except?: User): string[] => {
+    // Room for two more digits, cut between characters rather than UTF-16 units.
+    const base = [...nick].slice(0, MAX_NICK_LEN - 2).join('');
+    const candidates = [`${base}_`, ...Array.from({ length: 98 }, (_, i) => `${base}${i + 2}`)];
+    return candidates.filter((c) => nickProblem(c) === null && !nickHolder(c, except)).slice(0, NICK_SUGGESTIONS);
+};
+
+// Same rules as `normalize_room` in YewChat/protocol.
+const normalizeRoom = (name: string): string | null => {
+    const trimmed = String(name).trim();
+    const room = (trimmed.startsWith('#') ? trimmed.slice(1) : trimmed).toLowerCase();
+    return room.length > 0 && room.length <= MAX_ROOM_LEN && /^[a-z0-9_-]+$/.test(room) ? room : null;
+};
+
+const join = (user: User, name: string) => {
+    const room = normalizeRoom(name);
+    if (!room) {
+        send(user.ws, { type: 'error', code: 'invalidRoom', message: `"${name}" is not a valid room name` });
+        return;
+    }
+    const isNewRoom = !roomNames().includes(room);
+    user.rooms.add(room);
+    broadcastMembers(room);
+    if (isNewRoom) {
+        broadcastRooms();
+    } else {
+        send(user.ws, { type: 'rooms', rooms: roomNames() });
+    }
+};
+
+const leave = (user: User, room: string) => {
+    if (room === DEFAULT_ROOM || !user.rooms.delete(room)) {
+        return;
+    }
+    broadcastMembers(room);
+    if (!roomNames().includes(room)) {
+        broadcastRooms();
+    }
+};
+
+// Keeps the claimed mentions that really appear in `text` as `@nick`, each once.
+const checkMentions = (text: string, mentions: unknown): string[] | undefined => {
+    if (!Array.isArray(mentions)) {
+        return undefined;
+    }
+    // Same rule as `mention::mentioned` in YewChat: trailing punctuation may follow the nick.
+    const words = String(text).split(/\s+/);
+    const kept = [...new Set(mentions.map(String))].filter((nick) =>
+        words.some((w) => w === `@${nick}` || w.replace(/[.,;:!?)'"]+$/, '') === `@${nick}`),
+    );
+    return kept.length > 0 ? kept : undefined;
+};
+
+const message = (sender: User, frame: Extract<ClientFrame, { type: 'message' }>) => {
+    const { channel, text, replyTo } = frame;
+    const clientId = typeof frame.clientId === 'string' ? frame.clientId : undefined;
+    const seenKey = `${sender.author}\n${clientId}`;
+    const seen = clientId === undefined ? undefined : clientIds.get(seenKey);
+    if (seen) {
+        // Resent after a reconnect: answer with what we stored the first time.
+        const stored = (history.get(historyKey(sender, seen.channel)) ?? []).find((m) => m.id === seen.id);
+        if (stored) {
+            send(sender.ws, { type: 'message', ...stored, clientId });
+        }
+        return;
+    }
+    if (!textFits(sender, text, clientId)) {
+        return;
+    }
+    const stored: ChatMessage = { id: nextId++, channel, from: sender.nick, author: sender.author, text, time: Date.now() };
+    const mentioned = checkMentions(text, frame.mentions);
+    if (mentioned) {
+        stored.mentions = mentioned;
+    }
+    if (frame.emote === true) {
+        stored.emote = true;
+    }
+    if (replyTo !== undefined) {
+        const parent = (history.get(historyKey(sender, channel)) ?? []).find((m) => m.id === replyTo);
+        if (!parent) {
+            send(sender.ws, { type: 'error', code: 'unknownMessage', message: `no message ${replyTo} to reply to`, clientId });
+            return;
+        }
+        // Threads are one level deep: a reply to a reply joins the parent's thread.
+        stored.replyTo = parent.replyTo ?? parent.id;
+    }
+    if (canPost(sender, channel, clientId)) {
+        deliver(sender, channel, stored, clientId);
+        if (clientId !== undefined) {
+            clientIds.set(seenKey, { channel, id: stored.id });
+            if (clientIds.size > CLIENT_IDS_KEPT) {
+                clientIds.delete(clientIds.keys().next().value as string);
+            }
+        }
+    }
+};
+
+// Whether `text` is short enough to send on, telling `sender` if it isn't.
+const textFits = (sender: User, text: unknown, clientId?: string): text is string => {
+    if (typeof text !== 'string' || text.length > MAX_MESSAGE_LEN) {
+        send(sender.ws, {
+            type: 'error',
+            code: 'tooLarge',
+            message: `messages are limited to ${MAX_MESSAGE_LEN} characters`,
+            clientId,
+        });
+        return false;
+    }
+    return true;
+};
+
+// Whether `sender` may post to `channel` right now, telling them why not if they can't.
+const canPost = (sender: User, channel: Channel, clientId?: string): boolean => {
+    if ('direct' in channel) {
+        if (!users.some((u) => u.nick === channel.direct)) {
+            send(sender.ws, { type: 'error', code: 'unknownUser', message: `${channel.direct} is not online`, clientId });
+            return false;
+        }
+        return true;
+    }
+    if (!sender.rooms.has(channel.room)) {
+        send(sender.ws, { type: 'error', code: 'notInRoom', message: `join #${channel.room} first`, clientId });
+        return false;
+    }
+    return true;
+};
+
+// Stores a new message and sends it to everyone in `channel`, acknowledging
+// `clientId` to the connection that sent it. Check `canPost` first.
+const deliver = (sender: User, channel: Channel, stored: ChatMessage, clientId?: string) => {
+    remember(historyKey(sender, channel), stored);
+    // The sender's other tabs need their copy too.
+    const recipients =
+        'direct' in channel
+            ? new Set([...users.filter((u) => u.nick === channel.direct), ...users.filter((u) => u.author === sender.author)])
+            : new Set(membersOf(channel.room));
+    recipients.delete(sender);
+    const data = JSON.stringify({ type: 'message', ...stored } as ServerFrame);
+    recipients.forEach((u) => {
+        if (u.ws.readyState === WebSocket.OPEN) {
+            u.ws.send(data);
+        }
+    });
+    send(sender.ws, { type: 'message', ...stored, clientId });
+};
+
+const startUpload = (sender: User, frame: Extract<ClientFrame, { type: 'upload' }>) => {
+    const { channel, upload } = frame;
+    const size = Number(frame.size);
+    if (!Number.isInteger(size) || size <= 0 || size > MAX_UPLOAD_BYTES) {
+        send(sender.ws, {
+            type: 'uploadFailed',
+            upload,
+            code: 'tooLarge',
+            message: `files are limited to ${MAX_UPLOAD_BYTES} bytes`,
+        });
+        return;
+    }
+    if (!canPost(sender, channel)) {
+        send(sender.ws, { type: 'uploadFailed', upload, code: 'badFrame', message: 'cannot post here' });
+        return;
+    }
+    const name = String(frame.name).trim().slice(0, MAX_FILE_NAME_LEN) || 'file';
+    sender.uploads.set(upload, { channel, name, mime: checkMime(frame.mime), size, parts: [], received: 0 });
+};
+
+// The mime ends up as the download's Content-Type header, where anything but a plain
+// `type/subtype` (a stray CR/LF, say) would make `writeHead` throw.
+const checkMime = (mime: unknown): string => {
+    const candidate = String(mime ?? '').trim().toLowerCase();
+    return /^[\w.+-]+\/[\w.+-]+$/.test(candidate) ? candidate : 'application/octet-stream';
+};
+
+// Binary messages are `Chunk`s: upload id and chunk index as big-endian u32s, then data.
+const receiveChunk = (sender: User, data: Buffer) => {
+    if (data.length < 8) {
+        send(sender.ws, { type: 'error', code: 'badFrame', message: 'chunk is missing its header' });
+        return;
+    }
+    const id = data.readUInt32BE(0);
+    const index = data.readUInt32BE(4);
+    const payload = data.subarray(8);
+    const upload = sender.uploads.get(id);
+    const fail = (code: ErrorCode, message: string) => {
+        sender.uploads.delete(id);
+        send(sender.ws, { type: 'uploadFailed', upload: id, code, message });
+    };
+    if (!upload) {
+        fail('unknownUpload', `no upload ${id} in progress`);
+        return;
+    }
+    if (index !== upload.parts.length || payload.length > UPLOAD_CHUNK_BYTES) {
+        fail('unknownUpload', `expected chunk ${upload.parts.length} of at most ${UPLOAD_CHUNK_BYTES} bytes`);
+        return;
+    }
+    upload.parts.push(payload);
+    upload.received += payload.length;
+    if (upload.received > upload.size) {
+        fail('tooLarge', `more than the announced ${upload.size} bytes`);
+        return;
+    }
+    send(sender.ws, { type: 'uploadProgress', upload: id, received: upload.received });
+    if (upload.received < upload.size) {
+        return;
+    }
+    sender.uploads.delete(id);
+    // The room may have been left, or the DM peer gone, while the file was on its way.
+    if (!canPost(sender, upload.channel)) {
+        return;
+    }
+    const attachment: Attachment = { id: randomUUID(), name: upload.name, mime: upload.mime, size: upload.size };
+    keepFile(attachment.id, { name: upload.name, mime: upload.mime, data: Buffer.concat(upload.parts) });
+    deliver(sender, upload.channel, {
+        id: nextId++,
+        channel: upload.channel,
+        from: sender.nick,
+        author: sender.author,
+        text: '',
+        time: Date.now(),
+        attachment,
+    });
+};
+
+const keepFile = (id: string, file: StoredFile) => {
+    files.set(id, file);
+    let total = 0;
+    files.forEach((f) => (total += f.data.length));
+    for (const [oldest, f] of files) {
+        if (total <= FILES_KEPT_BYTES) {
+            break;
+        }
+        files.delete(oldest);
+        total -= f.data.length;
+    }
+};
+
+// The stored message `id` in `channel`, or null after telling `sender` it doesn't exist.
+const findMessage = (sender: User, channel: Channel, id: number): { kept: ChatMessage[]; index: number } | null => {
+    const kept = history.get(historyKey(sender, channel)) ?? [];
+    const index = kept.findIndex((m) => m.id === id);
+    if (index < 0 || kept[index].deleted) {
+        send(sender.ws, { type: 'error', code: 'unknownMessage', message: `no message ${id} here` });
+        return null;
+    }
+    return { kept, index };
+};
+
+// Everyone who could see `channel` gets the changed copy.
+const sendUpdate = (sender: User, channel: Channel, message: ChatMessage) => {
+    const frame: ServerFrame = { type: 'update', ...message };
+    const audience =
+        'room' in channel
+            ? membersOf(channel.room)
+            : users.filter((u) => u.author === sender.author || u.author === authorOf(channel.direct));
+    audience.forEach((u) => send(u.ws, frame));
+};
+
+// Only the author may edit or delete a message, not whoever took their nick since.
+const update = (sender: User, channel: Channel, id: number, change: (m: ChatMessage) => ChatMessage) => {
+    const found = findMessage(sender, channel, id);
+    if (!found) {
+        return;
+    }
+    const { kept, index } = found;
+    if (kept[index].author !== sender.author) {
+        send(sender.ws, { type: 'error', code: 'notAuthor', message: 'you can only change your own messages' });
+        return;
+    }
+    kept[index] = { ...change(kept[index]), edited: Date.now() };
+    sendUpdate(sender, channel, kept[index]);
+};
+
+const react = (sender: User, channel: Channel, id: number, emoji: string, add: boolean) => {
+    emoji = String(emoji);
+    if (emoji.length === 0 || emoji.length > MAX_REACTION_LEN || /\s/.test(emoji)) {
+        send(sender.ws, { type: 'error', code: 'badFrame', message: 'not a valid reaction' });
+        return;
+    }
+    const found = findMessage(sender, channel, id);
+    if (!found) {
+        return;
+    }
+    const { kept, index } = found;
+    const reactions = { ...(kept[index].reactions ?? {}) };
+    const who = (reactions[emoji] ?? []).filter((nick) => nick !== sender.nick);
+    if (add) {
+        who.push(sender.nick);
+    }
+    if (who.length > 0) {
+        reactions[emoji] = who;
+    } else {
+        delete reactions[emoji];
+    }
+    kept[index] = { ...kept[index], reactions };
+    sendUpdate(sender, channel, kept[index]);
+};
+
+// Relayed to everyone else in the conversation. Clients expire indicators on their
+// own, so a lost `typing: false` is harmless and nothing is reported back.
+const typing = (sender: User, channel: Channel, isTyping: boolean) => {
+    const frame: ServerFrame = { type: 'typing', channel, from: sender.nick, typing: Boolean(isTyping) };
+    const recipients =
+        'room' in channel
+            ? sender.rooms.has(channel.room)
+                ? membersOf(channel.room)
+                : []
+            : users.filter((u) => u.nick === channel.direct);
+    recipients.filter((u) => u.nick !== sender.nick).forEach((u) => send(u.ws, frame));
+};
+
+// Both ends of a DM share one history, whichever of them asks. It is filed under
+// their authors, so it follows them through renames and isn't handed on with a nick.
+const historyKey = (user: User, channel: Channel): string => {
+    if ('room' in channel) {
+        return `room:${channel.room}`;
+    }
+    const peer = authorOf(channel.direct) ?? `nick:${channel.direct}`;
+    return `dm:${[user.author, peer].sort().join('\u0000')}`;
+};
+
+const remember = (key: string, message: ChatMessage) => {
+    const kept = history.get(key) ?? [];
+    kept.push(message);
+    history.set(key, kept.slice(-HISTORY_KEPT));
+};
+
+const sendHistory = (user: User, channel: Channel, before: number | undefined, limit: number) => {
+    if ('room' in channel && !user.rooms.has(channel.room)) {
+        send(user.ws, { type: 'error', code: 'notInRoom', message: `join #${channel.room} first` });
+        return;
+    }
+    const kept = history.get(historyKey(user, channel)) ?? [];
+    const older = before === undefined ? kept : kept.filter((m) => m.id < before);
+    const size = Math.max(1, Math.min(limit, MAX_HISTORY_PAGE));
+    const messages = older.slice(-size);
+    send(user.ws, { type: 'history', channel, messages, complete: messages.length === older.length });
+};
+
+const roomNames = (): string[] => {
+    const names = new Set<string>([DEFAULT_ROOM]);
+    users.forEach((u) => u.rooms.forEach((r) => names.add(r)));
+    return Array.from(names).sort();
+};
+
+const membersOf = (room: string): User[] => users.filter((u) => u.rooms.has(room));
+
+// Frees the nicks of `gone` and tells everyone who is left.
+const removeUsers = (gone: User[]) => {
+    if (gone.length === 0) {
+        return;
+    }
+    users = users.filter((u) => !gone.includes(u));
+    broadcastUsers();
+    const affected = new Set<string>();
+    gone.forEach((u) => u.rooms.forEach((r) => affected.add(r)));
+    affected.forEach((room) => broadcastMembers(room));
+    broadcastRooms();
+};
+
+// Catches sockets that went away without a close event reaching us.
 const interval = setInterval(function ping() {
     const current_clients = Array.from(wss.clients);
-    const updated_users = users.filter((u) => current_clients.includes(u.ws));
-    if (updated_users.length !== users.length) {
-        users = updated_users;
-        broadcast(JSON.stringify({ messageType: 'users', dataArray: users.map((u) => u.nick) }));
-    }
+    removeUsers(users.filter((u) => !current_clients.includes(u.ws)));
 }, 5000);
 
-const broadcast = (data: any) => {
+const broadcastUsers = () => {
+    broadcast({ type: 'users', users: users.map((u) => u.nick) });
+};
+
+const broadcastMembers = (room: string) => {
+    broadcastToRoom(room, { type: 'members', room, users: membersOf(room).map((u) => u.nick) });
+};
+
+const broadcastRooms = () => {
+    broadcast({ type: 'rooms', rooms: roomNames() });
+};
+
+const send = (ws: WebSocket, frame: ServerFrame) => {
+    if (ws.readyState === WebSocket.OPEN) {
+        ws.send(JSON.stringify(frame));
+    }
+};
+
+const broadcastToRoom = (room: string, frame: ServerFrame) => {
+    const data = JSON.stringify(frame);
+    membersOf(room).forEach((u) => {
+        if (u.ws.readyState === WebSocket.OPEN) {
+            u.ws.send(data);
+        }
+    });
+};
+
+const broadcast = (frame: ServerFrame) => {
+    const data = JSON.stringify(frame);
     wss.clients.forEach((client) => {
         if (client.readyState === WebSocket.OPEN) {
             client.send(data);
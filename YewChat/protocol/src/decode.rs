@@ -0,0 +1,267 @@
+//! Tolerant decoding of incoming frames.
+//!
+//! Peers may be newer than us, so a frame with a `type` we don't know is reported
+//! separately from a frame that is actually broken and can be skipped safely.
+
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{ClientFrame, ServerFrame};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodeError {
+    /// Not JSON, or JSON that isn't an object.
+    Malformed(String),
+    /// An object without a string `type` field.
+    MissingType,
+    /// A `type` this version of the protocol doesn't define.
+    UnknownType(String),
+    /// A known `type` whose fields don't match its definition.
+    InvalidPayload { frame_type: String, reason: String },
+}
+
+impl DecodeError {
+    /// Unknown frames are expected when talking to a newer peer and are safe to ignore.
+    pub fn is_unknown_type(&self) -> bool {
+        matches!(self, DecodeError::UnknownType(_))
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Malformed(e) => write!(f, "malformed frame: {}", e),
+            DecodeError::MissingType => write!(f, "frame has no `type` field"),
+            DecodeError::UnknownType(t) => write!(f, "unknown frame type `{}`", t),
+            DecodeError::InvalidPayload { frame_type, reason } => {
+                write!(f, "invalid `{}` frame: {}", frame_type, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl ClientFrame {
+    /// Every `type` tag a [`ClientFrame`] can carry.
+    pub const TYPES: &'static [&'static str] = &[
+        "register", "join", "leave", "message", "history", "typing", "edit", "delete", "react", "unreact",
+        "upload",
+    ];
+
+    pub fn decode(raw: &str) -> Result<Self, DecodeError> {
+        decode(raw, Self::TYPES)
+    }
+}
+
+impl ServerFrame {
+    /// Every `type` tag a [`ServerFrame`] can carry.
+    pub const TYPES: &'static [&'static str] = &[
+        "registerResult", "users", "members", "rooms", "message", "update", "history", "typing",
+        "uploadProgress", "uploadFailed", "error",
+    ];
+
+    pub fn decode(raw: &str) -> Result<Self, DecodeError> {
+        decode(raw, Self::TYPES)
+    }
+}
+
+fn decode<T: DeserializeOwned>(raw: &str, known: &[&str]) -> Result<T, DecodeError> {
+    let value: Value = serde_json::from_str(raw).map_err(|e| DecodeError::Malformed(e.to_string()))?;
+    if !value.is_object() {
+        return Err(DecodeError::Malformed("expected a JSON object".into()));
+    }
+    let frame_type = value
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or(DecodeError::MissingType)?
+        .to_string();
+    if !known.contains(&frame_type.as_str()) {
+        return Err(DecodeError::UnknownType(frame_type));
+    }
+    serde_json::from_value(value).map_err(|e| DecodeError::InvalidPayload {
+        frame_type,
+        reason: e.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Channel, ChatMessage, ErrorCode, MessageId, RegisterStatus, SessionToken, Timestamp, UploadId};
+    use serde_json::json;
+
+    fn type_of(json: String) -> String {
+        let value: Value = serde_json::from_str(&json).unwrap();
+        value["type"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn type_lists_cover_every_variant() {
+        let client = [
+            ClientFrame::register("alice", &SessionToken("s".into())),
+            ClientFrame::Join { room: "a".into() },
+            ClientFrame::Leave { room: "a".into() },
+            ClientFrame::Message {
+                channel: Channel::Room("a".into()),
+                text: "hi".into(),
+                reply_to: None,
+                mentions: vec![],
+                emote: false,
+                client_id: None,
+            },
+            ClientFrame::History {
+                channel: Channel::Room("a".into()),
+                before: None,
+                limit: 1,
+            },
+            ClientFrame::Typing {
+                channel: Channel::Room("a".into()),
+                typing: true,
+            },
+            ClientFrame::Edit {
+                channel: Channel::Room("a".into()),
+                id: MessageId(0),
+                text: "b".into(),
+                mentions: vec![],
+            },
+            ClientFrame::Delete {
+                channel: Channel::Room("a".into()),
+                id: MessageId(0),
+            },
+            ClientFrame::React {
+                channel: Channel::Room("a".into()),
+                id: MessageId(0),
+                emoji: "b".into(),
+            },
+            ClientFrame::Unreact {
+                channel: Channel::Room("a".into()),
+                id: MessageId(0),
+                emoji: "b".into(),
+            },
+            ClientFrame::Upload {
+                channel: Channel::Room("a".into()),
+                upload: UploadId(0),
+                name: "a.png".into(),
+                mime: "image/png".into(),
+                size: 1,
+            },
+        ];
+        for frame in client {
+            assert!(ClientFrame::TYPES.contains(&type_of(frame.to_json()).as_str()));
+        }
+
+        let message = ChatMessage {
+            id: MessageId(0),
+            channel: Channel::Direct("a".into()),
+            from: "a".into(),
+            text: "b".into(),
+            time: Timestamp(0),
+            reply_to: None,
+            edited: None,
+            deleted: false,
+            reactions: Default::default(),
+            mentions: vec![],
+            emote: false,
+            attachment: None,
+            client_id: None,
+            author: None,
+        };
+        let server = [
+            ServerFrame::RegisterResult {
+                status: RegisterStatus::Accepted,
+                nick: "a".into(),
+                author: None,
+                message: String::new(),
+                suggestions: vec![],
+            },
+            ServerFrame::Users { users: vec![] },
+            ServerFrame::Members {
+                room: "a".into(),
+                users: vec![],
+            },
+            ServerFrame::Rooms { rooms: vec![] },
+            ServerFrame::Message(message.clone()),
+            ServerFrame::Update(message),
+            ServerFrame::History {
+                channel: Channel::Room("a".into()),
+                messages: vec![],
+                complete: false,
+            },
+            ServerFrame::Typing {
+                channel: Channel::Room("a".into()),
+                from: "a".into(),
+                typing: false,
+            },
+            ServerFrame::UploadProgress {
+                upload: UploadId(0),
+                received: 1,
+            },
+            ServerFrame::UploadFailed {
+                upload: UploadId(0),
+                code: ErrorCode::TooLarge,
+                message: "x".into(),
+            },
+            ServerFrame::Error {
+                code: ErrorCode::BadFrame,
+                message: "x".into(),
+                client_id: None,
+            },
+        ];
+        for frame in server {
+            assert!(ServerFrame::TYPES.contains(&type_of(frame.to_json()).as_str()));
+        }
+    }
+
+    #[test]
+    fn decodes_known_frames() {
+        let frame = ServerFrame::decode(r#"{"type":"users","users":["bob"]}"#).unwrap();
+        assert_eq!(frame, ServerFrame::Users { users: vec!["bob".into()] });
+    }
+
+    #[test]
+    fn ignores_extra_fields() {
+        let raw = json!({"type": "users", "users": [], "addedLater": true}).to_string();
+        assert!(ServerFrame::decode(&raw).is_ok());
+    }
+
+    #[test]
+    fn reports_unknown_types() {
+        let err = ServerFrame::decode(r#"{"type":"poll","question":"?"}"#).unwrap_err();
+        assert_eq!(err, DecodeError::UnknownType("poll".into()));
+        assert!(err.is_unknown_type());
+    }
+
+    #[test]
+    fn reports_malformed_frames() {
+        assert!(matches!(ServerFrame::decode("not json"), Err(DecodeError::Malformed(_))));
+        assert!(matches!(ServerFrame::decode("[1, 2]"), Err(DecodeError::Malformed(_))));
+        assert_eq!(ServerFrame::decode(r#"{"users":[]}"#), Err(DecodeError::MissingType));
+        assert_eq!(ServerFrame::decode(r#"{"type":7}"#), Err(DecodeError::MissingType));
+    }
+
+    #[test]
+    fn reports_invalid_payloads() {
+        let err = ServerFrame::decode(r#"{"type":"message","from":"bob"}"#).unwrap_err();
+        assert!(matches!(err, DecodeError::InvalidPayload { ref frame_type, .. } if frame_type == "message"));
+        assert!(!err.is_unknown_type());
+    }
+
+    #[test]
+    fn decodes_client_frames() {
+        let frame = ClientFrame::decode(r#"{"type":"message","channel":{"room":"a"},"text":"hi"}"#).unwrap();
+        assert_eq!(
+            frame,
+            ClientFrame::Message {
+                channel: Channel::Room("a".into()),
+                text: "hi".into(),
+                reply_to: None,
+                mentions: vec![],
+                emote: false,
+                client_id: None,
+            }
+        );
+    }
+}
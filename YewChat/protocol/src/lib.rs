@@ -0,0 +1,814 @@
+//! Frames exchanged between YewChat clients and the websocket server.
+//!
+//! Every frame is a single JSON text message tagged by its `type` field, e.g.
+//! `{"type":"message","channel":{"room":"global"},"text":"hi"}`. Clients announce [`PROTOCOL_VERSION`] when
+//! registering so the server can refuse clients it doesn't understand. File contents
+//! travel as binary messages instead, see [`Chunk`].
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+mod decode;
+mod nick;
+mod room;
+mod upload;
+
+pub use decode::DecodeError;
+pub use nick::{validate_nick, NickError, MAX_NICK_LEN, MIN_NICK_LEN, RESERVED_NICKS};
+pub use room::{normalize_room, DEFAULT_ROOM, MAX_ROOM_LEN};
+pub use upload::{
+    Attachment, Chunk, FileId, UploadId, CHUNK_HEADER_LEN, FILES_PATH, MAX_FILE_NAME_LEN, MAX_UPLOAD_BYTES,
+    UPLOAD_CHUNK_BYTES,
+};
+
+/// Bumped whenever a frame changes in a way older peers can't read.
+pub const PROTOCOL_VERSION: u32 = 14;
+
+/// Messages per `history` page unless the client asks for fewer.
+pub const HISTORY_PAGE_SIZE: u32 = 50;
+
+/// A typing indicator that isn't refreshed within this many milliseconds is
+/// dropped, so a lost `typing: false` can't leave it up forever.
+pub const TYPING_TIMEOUT_MS: u64 = 6_000;
+/// How often a client still typing repeats `typing: true`; well inside the timeout.
+pub const TYPING_REFRESH_MS: u64 = 2_500;
+
+/// Longest reaction, in UTF-16 code units as JavaScript counts them. Enough for
+/// any emoji including skin tones and ZWJ sequences, short enough to stay an emoji.
+pub const MAX_REACTION_LEN: usize = 16;
+
+/// Longest message text, in UTF-16 code units as JavaScript counts them. Keeps
+/// one pasted message from costing every client in the room to render.
+pub const MAX_MESSAGE_LEN: usize = 4_000;
+
+/// Frames sent by a client.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ClientFrame {
+    /// Claims `nick` for this connection. Must be the first frame on every connection,
+    /// and is answered with [`ServerFrame::RegisterResult`]. Registered users are
+    /// placed in [`DEFAULT_ROOM`]. Sent again once registered, it renames the user;
+    /// under the same nick it just repeats the users, rooms and members frames.
+    /// `session` stays the same across reconnects and renames, see [`SessionToken`].
+    Register { nick: String, version: u32, session: SessionToken },
+    /// Enters `room`, creating it if nobody is in it yet.
+    Join { room: String },
+    Leave { room: String },
+    /// Posts `text` to a room, or privately to one user, optionally as a reply
+    /// in the thread started by `reply_to`. `mentions` lists the nicks `text`
+    /// addresses as `@nick`; `emote` marks a `/me` action. `client_id` is echoed
+    /// back to the sender with the stored message, and a repeat of an id the
+    /// server already stored is answered with that message instead of a copy.
+    Message {
+        channel: Channel,
+        text: String,
+        #[serde(default, rename = "replyTo", skip_serializing_if = "Option::is_none")]
+        reply_to: Option<MessageId>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        mentions: Vec<String>,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        emote: bool,
+        #[serde(default, rename = "clientId", skip_serializing_if = "Option::is_none")]
+        client_id: Option<ClientId>,
+    },
+    /// Asks for up to `limit` messages in `channel` older than `before`, or the
+    /// latest ones when `before` is absent. Answered with [`ServerFrame::History`].
+    History {
+        channel: Channel,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        before: Option<MessageId>,
+        limit: u32,
+    },
+    /// Starts or stops the "is typing" indicator in `channel`.
+    Typing { channel: Channel, typing: bool },
+    /// Replaces the text, and with it the mentions, of one of our own messages.
+    Edit {
+        channel: Channel,
+        id: MessageId,
+        text: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        mentions: Vec<String>,
+    },
+    /// Removes one of our own messages, leaving a tombstone in its place.
+    Delete { channel: Channel, id: MessageId },
+    /// Adds our `emoji` reaction to a message.
+    React { channel: Channel, id: MessageId, emoji: String },
+    /// Takes our `emoji` reaction back.
+    Unreact { channel: Channel, id: MessageId, emoji: String },
+    /// Announces a file of `size` bytes for `channel`, to follow as [`Chunk`]s of `upload`.
+    /// Once all of it has arrived it is posted as a message with an [`Attachment`].
+    Upload {
+        channel: Channel,
+        upload: UploadId,
+        name: String,
+        mime: String,
+        size: u64,
+    },
+}
+
+impl ClientFrame {
+    pub fn register(nick: impl Into<String>, session: &SessionToken) -> Self {
+        ClientFrame::Register {
+            nick: nick.into(),
+            version: PROTOCOL_VERSION,
+            session: session.clone(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("client frames always serialize")
+    }
+}
+
+/// Frames sent by the server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ServerFrame {
+    /// Answers [`ClientFrame::Register`]. Until a nick is accepted every other frame
+    /// is refused with [`ErrorCode::NotRegistered`]. `suggestions` are free nicks
+    /// close to the one asked for. An accepted nick comes with the [`AuthorId`] our
+    /// messages carry.
+    RegisterResult {
+        status: RegisterStatus,
+        nick: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        author: Option<AuthorId>,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        message: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        suggestions: Vec<String>,
+    },
+    /// Everyone currently connected, sent whenever somebody connects or disconnects.
+    Users { users: Vec<String> },
+    /// Everyone in `room`, sent to its members whenever that changes.
+    Members { room: String, users: Vec<String> },
+    /// Every room that currently has somebody in it.
+    Rooms { rooms: Vec<String> },
+    /// A message posted by `from`. Direct messages are sent to both ends.
+    Message(ChatMessage),
+    /// A page of stored messages, oldest first. `complete` is set once there is
+    /// nothing older left to ask for.
+    History {
+        channel: Channel,
+        messages: Vec<ChatMessage>,
+        complete: bool,
+    },
+    /// A message that was edited, deleted or reacted to, replacing the copy with the same id.
+    Update(ChatMessage),
+    /// `from` started or stopped typing in `channel`. Never echoed to `from`.
+    Typing { channel: Channel, from: String, typing: bool },
+    /// Acknowledges a [`Chunk`]: `received` bytes of `upload` have arrived so far.
+    UploadProgress { upload: UploadId, received: u64 },
+    /// The server gave up on `upload` and dropped what it had received.
+    UploadFailed {
+        upload: UploadId,
+        code: ErrorCode,
+        message: String,
+    },
+    /// The server refused the last frame; `client_id` names the message it refused, if any.
+    Error {
+        code: ErrorCode,
+        message: String,
+        #[serde(default, rename = "clientId", skip_serializing_if = "Option::is_none")]
+        client_id: Option<ClientId>,
+    },
+}
+
+impl ServerFrame {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("server frames always serialize")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    pub id: MessageId,
+    pub channel: Channel,
+    pub from: String,
+    pub text: String,
+    /// When the server received the message.
+    pub time: Timestamp,
+    /// The message that started the thread this one replies in. Threads are one
+    /// level deep: replying to a reply lands in the same thread.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<MessageId>,
+    /// When the author last changed the text, if they have.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited: Option<Timestamp>,
+    /// The author deleted this message and `text` is empty.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deleted: bool,
+    /// Who reacted with each emoji, in the order they did.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub reactions: BTreeMap<String, Vec<String>>,
+    /// Nicks the message addresses as `@nick`, each once.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<String>,
+    /// Sent with `/me`: `text` describes what `from` is doing, as in "* alice waves".
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub emote: bool,
+    /// A file posted with the message; `text` is then usually empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<Attachment>,
+    /// What the author sent it as; only on the copy echoed back to them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<ClientId>,
+    /// Who sent it, whatever nick they go by now. Missing on messages stored
+    /// before authors were tracked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<AuthorId>,
+}
+
+impl ChatMessage {
+    /// Whether `self` was sent by us, given the nick and [`AuthorId`] we have now.
+    /// Authors outlast renames and aren't handed on with a freed nick; the nick
+    /// only decides for messages that have no author.
+    pub fn is_from(&self, nick: &str, author: Option<&AuthorId>) -> bool {
+        match (&self.author, author) {
+            (Some(theirs), Some(ours)) => theirs == ours,
+            _ => self.from == nick,
+        }
+    }
+}
+
+/// Whether `emoji` is acceptable as a reaction: non-empty, short, no whitespace.
+pub fn valid_reaction(emoji: &str) -> bool {
+    let len = emoji.encode_utf16().count();
+    len > 0 && len <= MAX_REACTION_LEN && !emoji.chars().any(char::is_whitespace)
+}
+
+/// Where a message is posted.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Channel {
+    /// Everyone who has joined the named room.
+    Room(String),
+    /// Only the sender and the named recipient.
+    Direct(String),
+}
+
+impl Channel {
+    /// The conversation a message belongs to from `me`'s point of view: direct
+    /// messages are addressed to the recipient, so our own replies have to be
+    /// filed under the person we sent them to and theirs under the sender.
+    pub fn conversation(&self, from: &str, me: &str) -> Channel {
+        match self {
+            Channel::Direct(_) if from != me => Channel::Direct(from.to_string()),
+            Channel::Direct(to) => Channel::Direct(to.clone()),
+            Channel::Room(room) => Channel::Room(room.clone()),
+        }
+    }
+}
+
+/// Assigned by the server, increasing in the order messages were received.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MessageId(pub u64);
+
+/// Picked by the sender before the server has assigned a [`MessageId`], so the
+/// echo can be matched to the message it acknowledges.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ClientId(pub String);
+
+/// A secret the client picks once per tab and registers with every time, so the
+/// server can tell the same user reconnecting or renaming from someone new taking
+/// a freed nick. Never shown to other users.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SessionToken(pub String);
+
+/// The public stand-in for a [`SessionToken`], derived from it by the server and
+/// attached to every message as its author.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AuthorId(pub String);
+
+/// Milliseconds since the Unix epoch, as produced by JavaScript's `Date.now()`.
+///
+/// Decoding refuses anything past [`Timestamp::MAX`], which a JavaScript `Date`
+/// can't hold and would throw on when formatted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "u64", into = "u64")]
+pub struct Timestamp(pub u64);
+
+impl Timestamp {
+    /// The latest instant a JavaScript `Date` can represent.
+    pub const MAX: Timestamp = Timestamp(8_640_000_000_000_000);
+
+    pub fn millis(self) -> u64 {
+        self.0
+    }
+}
+
+impl TryFrom<u64> for Timestamp {
+    type Error = String;
+
+    fn try_from(millis: u64) -> Result<Self, Self::Error> {
+        if millis > Timestamp::MAX.0 {
+            return Err(format!("time {} is past the latest representable date", millis));
+        }
+        Ok(Timestamp(millis))
+    }
+}
+
+impl From<Timestamp> for u64 {
+    fn from(time: Timestamp) -> u64 {
+        time.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RegisterStatus {
+    Accepted,
+    /// Somebody else is connected under that nick, compared ignoring case.
+    Taken,
+    /// The nick breaks the rules of [`validate_nick`].
+    Invalid,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCode {
+    /// The client's `PROTOCOL_VERSION` differs from the server's.
+    UnsupportedVersion,
+    /// A frame other than `register` arrived before registering.
+    NotRegistered,
+    /// The room name doesn't pass [`normalize_room`].
+    InvalidRoom,
+    /// Posting to a room the client hasn't joined.
+    NotInRoom,
+    /// A direct message to somebody who isn't connected.
+    UnknownUser,
+    /// Editing or deleting a message id the server doesn't hold.
+    UnknownMessage,
+    /// Editing or deleting somebody else's message.
+    NotAuthor,
+    /// An upload bigger than [`MAX_UPLOAD_BYTES`] or longer than it announced, or
+    /// message text longer than [`MAX_MESSAGE_LEN`].
+    TooLarge,
+    /// A chunk for an upload that wasn't announced, was abandoned, or arrived out of order.
+    UnknownUpload,
+    /// The frame wasn't valid JSON or didn't match any known shape.
+    BadFrame,
+    /// A code added in a newer protocol version.
+    #[serde(other)]
+    Other,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn round_trip_client(frame: ClientFrame) {
+        let decoded: ClientFrame = serde_json::from_str(&frame.to_json()).unwrap();
+        assert_eq!(decoded, frame);
+    }
+
+    fn round_trip_server(frame: ServerFrame) {
+        let decoded: ServerFrame = serde_json::from_str(&frame.to_json()).unwrap();
+        assert_eq!(decoded, frame);
+    }
+
+    #[test]
+    fn client_frames_round_trip() {
+        round_trip_client(ClientFrame::register("alice", &SessionToken("s3cret".into())));
+        round_trip_client(ClientFrame::Join { room: "rust".into() });
+        round_trip_client(ClientFrame::Leave { room: "rust".into() });
+        round_trip_client(ClientFrame::Message {
+            channel: Channel::Room(DEFAULT_ROOM.into()),
+            text: "hi \"there\"".into(),
+            reply_to: None,
+            mentions: vec![],
+            emote: false,
+            client_id: None,
+        });
+        round_trip_client(ClientFrame::Message {
+            channel: Channel::Room(DEFAULT_ROOM.into()),
+            text: "same".into(),
+            reply_to: Some(MessageId(4)),
+            mentions: vec![],
+            emote: false,
+            client_id: None,
+        });
+        round_trip_client(ClientFrame::History {
+            channel: Channel::Direct("bob".into()),
+            before: Some(MessageId(7)),
+            limit: HISTORY_PAGE_SIZE,
+        });
+        round_trip_client(ClientFrame::Typing {
+            channel: Channel::Room("rust".into()),
+            typing: true,
+        });
+        round_trip_client(ClientFrame::Edit {
+            channel: Channel::Room("rust".into()),
+            id: MessageId(3),
+            text: "fixed".into(),
+            mentions: vec![],
+        });
+        round_trip_client(ClientFrame::Delete {
+            channel: Channel::Direct("bob".into()),
+            id: MessageId(3),
+        });
+        round_trip_client(ClientFrame::React {
+            channel: Channel::Room("rust".into()),
+            id: MessageId(3),
+            emoji: "🎉".into(),
+        });
+        round_trip_client(ClientFrame::Unreact {
+            channel: Channel::Room("rust".into()),
+            id: MessageId(3),
+            emoji: "🎉".into(),
+        });
+        round_trip_client(ClientFrame::Upload {
+            channel: Channel::Direct("bob".into()),
+            upload: UploadId(1),
+            name: "screenshot.png".into(),
+            mime: "image/png".into(),
+            size: 70_000,
+        });
+    }
+
+    #[test]
+    fn server_frames_round_trip() {
+        round_trip_server(ServerFrame::Users {
+            users: vec!["alice".into(), "bob".into()],
+        });
+        round_trip_server(ServerFrame::Members {
+            room: "rust".into(),
+            users: vec!["alice".into()],
+        });
+        round_trip_server(ServerFrame::Rooms {
+            rooms: vec![DEFAULT_ROOM.into(), "rust".into()],
+        });
+        let message = ChatMessage {
+            id: MessageId(1),
+            channel: Channel::Room(DEFAULT_ROOM.into()),
+            from: "alice".into(),
+            text: "hello".into(),
+            time: Timestamp(1_700_000_000_000),
+            reply_to: Some(MessageId(0)),
+            edited: None,
+            deleted: false,
+            reactions: BTreeMap::from([("👍".to_string(), vec!["bob".to_string()])]),
+            mentions: vec![],
+            emote: false,
+            attachment: None,
+            client_id: None,
+            author: None,
+        };
+        round_trip_server(ServerFrame::Message(message.clone()));
+        round_trip_server(ServerFrame::Update(ChatMessage {
+            text: String::new(),
+            edited: Some(Timestamp(1_700_000_000_001)),
+            deleted: true,
+            ..message.clone()
+        }));
+        round_trip_server(ServerFrame::History {
+            channel: Channel::Room(DEFAULT_ROOM.into()),
+            messages: vec![message],
+            complete: true,
+        });
+        round_trip_server(ServerFrame::Typing {
+            channel: Channel::Direct("bob".into()),
+            from: "alice".into(),
+            typing: false,
+        });
+        round_trip_server(ServerFrame::UploadProgress {
+            upload: UploadId(1),
+            received: UPLOAD_CHUNK_BYTES,
+        });
+        round_trip_server(ServerFrame::UploadFailed {
+            upload: UploadId(1),
+            code: ErrorCode::TooLarge,
+            message: "files are limited to 8 MiB".into(),
+        });
+        round_trip_server(ServerFrame::Error {
+            code: ErrorCode::UnsupportedVersion,
+            message: "expected version 1".into(),
+            client_id: None,
+        });
+    }
+
+    // The server isn't written in Rust, so pin the exact JSON it has to produce and accept.
+    #[test]
+    fn register_wire_format() {
+        let value = serde_json::to_value(ClientFrame::register("alice", &SessionToken("s3cret".into()))).unwrap();
+        assert_eq!(
+            value,
+            json!({"type": "register", "nick": "alice", "version": PROTOCOL_VERSION, "session": "s3cret"})
+        );
+    }
+
+    #[test]
+    fn register_result_wire_format() {
+        let raw = r#"{"type":"registerResult","status":"accepted","nick":"alice","author":"a1b2"}"#;
+        assert_eq!(
+            ServerFrame::decode(raw).unwrap(),
+            ServerFrame::RegisterResult {
+                status: RegisterStatus::Accepted,
+                nick: "alice".into(),
+                author: Some(AuthorId("a1b2".into())),
+                message: String::new(),
+                suggestions: vec![],
+            }
+        );
+        let refused = ServerFrame::RegisterResult {
+            status: RegisterStatus::Taken,
+            nick: "bob".into(),
+            author: None,
+            message: "bob is already taken".into(),
+            suggestions: vec!["bob2".into(), "bob_".into()],
+        };
+        assert_eq!(
+            serde_json::to_value(refused).unwrap(),
+            json!({
+                "type": "registerResult",
+                "status": "taken",
+                "nick": "bob",
+                "message": "bob is already taken",
+                "suggestions": ["bob2", "bob_"],
+            })
+        );
+    }
+
+    #[test]
+    fn message_wire_format() {
+        let raw = r#"{"type":"message","id":3,"channel":{"room":"rust"},"from":"bob","text":"yo","time":42}"#;
+        let frame: ServerFrame = serde_json::from_str(raw).unwrap();
+        assert_eq!(
+            frame,
+            ServerFrame::Message(ChatMessage {
+                id: MessageId(3),
+                channel: Channel::Room("rust".into()),
+                from: "bob".into(),
+                text: "yo".into(),
+                time: Timestamp(42),
+                reply_to: None,
+                edited: None,
+                deleted: false,
+                reactions: BTreeMap::new(),
+                mentions: vec![],
+                emote: false,
+                attachment: None,
+                client_id: None,
+                author: None,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_times_past_what_javascript_can_hold() {
+        let at = |time: u64| {
+            let raw = r#"{"type":"message","id":3,"channel":{"room":"rust"},"from":"bob","text":"yo","time":TIME}"#;
+            raw.replace("TIME", &time.to_string())
+        };
+        assert!(ServerFrame::decode(&at(Timestamp::MAX.0)).is_ok());
+        assert!(ServerFrame::decode(&at(Timestamp::MAX.0 + 1)).is_err());
+        assert!(ServerFrame::decode(&at(u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn direct_message_wire_format() {
+        let frame = ClientFrame::Message {
+            channel: Channel::Direct("bob".into()),
+            text: "psst".into(),
+            reply_to: None,
+            mentions: vec![],
+            emote: false,
+            client_id: None,
+        };
+        assert_eq!(
+            serde_json::to_value(frame).unwrap(),
+            json!({"type": "message", "channel": {"direct": "bob"}, "text": "psst"})
+        );
+    }
+
+    #[test]
+    fn history_request_wire_format() {
+        let latest = ClientFrame::History {
+            channel: Channel::Room("rust".into()),
+            before: None,
+            limit: 20,
+        };
+        assert_eq!(
+            serde_json::to_value(latest).unwrap(),
+            json!({"type": "history", "channel": {"room": "rust"}, "limit": 20})
+        );
+        let older = ClientFrame::decode(r#"{"type":"history","channel":{"room":"rust"},"before":9,"limit":20}"#);
+        assert!(matches!(older, Ok(ClientFrame::History { before: Some(MessageId(9)), .. })));
+    }
+
+    #[test]
+    fn update_wire_format() {
+        let message = ChatMessage {
+            id: MessageId(5),
+            channel: Channel::Room("rust".into()),
+            from: "bob".into(),
+            text: String::new(),
+            time: Timestamp(42),
+            reply_to: None,
+            edited: Some(Timestamp(50)),
+            deleted: true,
+            reactions: BTreeMap::new(),
+            mentions: vec![],
+            emote: false,
+            attachment: None,
+            client_id: None,
+            author: None,
+        };
+        assert_eq!(
+            serde_json::to_value(ServerFrame::Update(message)).unwrap(),
+            json!({
+                "type": "update", "id": 5, "channel": {"room": "rust"}, "from": "bob",
+                "text": "", "time": 42, "edited": 50, "deleted": true
+            })
+        );
+    }
+
+    #[test]
+    fn reactions_wire_format() {
+        let raw = r#"{"type":"update","id":5,"channel":{"room":"rust"},"from":"bob","text":"hi","time":42,"reactions":{"👍":["alice","carol"]}}"#;
+        let frame: ServerFrame = serde_json::from_str(raw).unwrap();
+        let ServerFrame::Update(message) = frame else { panic!("expected an update") };
+        assert_eq!(message.reactions["👍"], vec!["alice".to_string(), "carol".to_string()]);
+    }
+
+    #[test]
+    fn reaction_validation() {
+        assert!(valid_reaction("👍"));
+        assert!(valid_reaction("👍🏽"));
+        assert!(valid_reaction("👩‍👩‍👧‍👦"));
+        assert!(!valid_reaction(""));
+        assert!(!valid_reaction("a b"));
+        assert!(!valid_reaction("this is not an emoji at all"));
+    }
+
+    #[test]
+    fn upload_wire_format() {
+        let frame = ClientFrame::Upload {
+            channel: Channel::Room("rust".into()),
+            upload: UploadId(2),
+            name: "a.txt".into(),
+            mime: "text/plain".into(),
+            size: 5,
+        };
+        assert_eq!(
+            serde_json::to_value(frame).unwrap(),
+            json!({"type": "upload", "channel": {"room": "rust"}, "upload": 2, "name": "a.txt", "mime": "text/plain", "size": 5})
+        );
+        let progress = ServerFrame::decode(r#"{"type":"uploadProgress","upload":2,"received":5}"#);
+        assert_eq!(
+            progress,
+            Ok(ServerFrame::UploadProgress {
+                upload: UploadId(2),
+                received: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn attachment_wire_format() {
+        let raw = r#"{"type":"message","id":3,"channel":{"room":"rust"},"from":"bob","text":"","time":42,
+            "attachment":{"id":"0f8e","name":"a.png","mime":"image/png","size":10}}"#;
+        let ServerFrame::Message(message) = ServerFrame::decode(raw).unwrap() else { panic!("expected a message") };
+        let attachment = message.attachment.unwrap();
+        assert_eq!(attachment.id, FileId("0f8e".into()));
+        assert_eq!(attachment.path(), "/files/0f8e");
+    }
+
+    #[test]
+    fn reply_wire_format() {
+        let frame = ClientFrame::Message {
+            channel: Channel::Room("rust".into()),
+            text: "agreed".into(),
+            reply_to: Some(MessageId(12)),
+            mentions: vec![],
+            emote: false,
+            client_id: None,
+        };
+        assert_eq!(
+            serde_json::to_value(frame).unwrap(),
+            json!({"type": "message", "channel": {"room": "rust"}, "text": "agreed", "replyTo": 12})
+        );
+    }
+
+    #[test]
+    fn mentions_wire_format() {
+        let frame = ClientFrame::Message {
+            channel: Channel::Room("rust".into()),
+            text: "@bob look".into(),
+            reply_to: None,
+            emote: false,
+            mentions: vec!["bob".into()],
+            client_id: None,
+        };
+        assert_eq!(
+            serde_json::to_value(frame).unwrap(),
+            json!({"type": "message", "channel": {"room": "rust"}, "text": "@bob look", "mentions": ["bob"]})
+        );
+        let raw = r#"{"type":"message","id":3,"channel":{"room":"rust"},"from":"alice","text":"@bob look","time":42,"mentions":["bob"]}"#;
+        let ServerFrame::Message(message) = ServerFrame::decode(raw).unwrap() else { panic!("expected a message") };
+        assert_eq!(message.mentions, vec!["bob".to_string()]);
+    }
+
+    #[test]
+    fn emote_wire_format() {
+        let frame = ClientFrame::Message {
+            channel: Channel::Room("rust".into()),
+            text: "waves".into(),
+            reply_to: None,
+            mentions: vec![],
+            emote: true,
+            client_id: None,
+        };
+        assert_eq!(
+            serde_json::to_value(frame).unwrap(),
+            json!({"type": "message", "channel": {"room": "rust"}, "text": "waves", "emote": true})
+        );
+    }
+
+    #[test]
+    fn renamed_author_still_owns_their_messages() {
+        let raw = r#"{"type":"message","id":3,"channel":{"room":"rust"},"from":"alice","author":"a1","text":"hi","time":42}"#;
+        let ServerFrame::Message(message) = ServerFrame::decode(raw).unwrap() else { panic!("expected a message") };
+        let (alice, carol) = (AuthorId("a1".into()), AuthorId("c3".into()));
+        // Alice went on as alice2 and can still edit it; carol, who took the nick, can't.
+        assert!(message.is_from("alice2", Some(&alice)));
+        assert!(!message.is_from("alice", Some(&carol)));
+        // Without an author the nick is all there is to go on.
+        let legacy = ChatMessage { author: None, ..message };
+        assert!(legacy.is_from("alice", Some(&carol)));
+        assert!(!legacy.is_from("alice2", Some(&alice)));
+    }
+
+    #[test]
+    fn client_id_wire_format() {
+        let frame = ClientFrame::Message {
+            channel: Channel::Room("rust".into()),
+            text: "hi".into(),
+            reply_to: None,
+            mentions: vec![],
+            emote: false,
+            client_id: Some(ClientId("k3x-1".into())),
+        };
+        assert_eq!(
+            serde_json::to_value(frame).unwrap(),
+            json!({"type": "message", "channel": {"room": "rust"}, "text": "hi", "clientId": "k3x-1"})
+        );
+        let raw = r#"{"type":"message","id":3,"channel":{"room":"rust"},"from":"alice","text":"hi","time":42,"clientId":"k3x-1"}"#;
+        let ServerFrame::Message(message) = ServerFrame::decode(raw).unwrap() else { panic!("expected a message") };
+        assert_eq!(message.client_id, Some(ClientId("k3x-1".into())));
+        let raw = r#"{"type":"error","code":"notInRoom","message":"join first","clientId":"k3x-1"}"#;
+        let ServerFrame::Error { client_id, .. } = ServerFrame::decode(raw).unwrap() else { panic!("expected an error") };
+        assert_eq!(client_id, Some(ClientId("k3x-1".into())));
+    }
+
+    #[test]
+    fn typing_wire_format() {
+        let raw = r#"{"type":"typing","channel":{"room":"rust"},"from":"bob","typing":true}"#;
+        let frame: ServerFrame = serde_json::from_str(raw).unwrap();
+        assert_eq!(
+            frame,
+            ServerFrame::Typing {
+                channel: Channel::Room("rust".into()),
+                from: "bob".into(),
+                typing: true,
+            }
+        );
+    }
+
+    #[test]
+    fn direct_messages_file_under_the_peer() {
+        let to_bob = Channel::Direct("bob".into());
+        assert_eq!(to_bob.conversation("alice", "alice"), Channel::Direct("bob".into()));
+        assert_eq!(to_bob.conversation("alice", "bob"), Channel::Direct("alice".into()));
+        let room = Channel::Room("rust".into());
+        assert_eq!(room.conversation("alice", "bob"), room);
+    }
+
+    #[test]
+    fn error_wire_format() {
+        let raw = r#"{"type":"error","code":"notRegistered","message":"register first"}"#;
+        let frame: ServerFrame = serde_json::from_str(raw).unwrap();
+        assert_eq!(
+            frame,
+            ServerFrame::Error {
+                code: ErrorCode::NotRegistered,
+                message: "register first".into(),
+                client_id: None,
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_error_codes_are_tolerated() {
+        let raw = r#"{"type":"error","code":"rateLimited","message":"slow down"}"#;
+        let frame: ServerFrame = serde_json::from_str(raw).unwrap();
+        assert!(matches!(frame, ServerFrame::Error { code: ErrorCode::Other, .. }));
+    }
+}
@@ -0,0 +1,112 @@
+//! Nick rules, shared so the login form can explain a refusal before the server makes one.
+
+use std::fmt;
+
+/// Bounds on a nick's length, in characters.
+pub const MIN_NICK_LEN: usize = 2;
+pub const MAX_NICK_LEN: usize = 24;
+
+/// Nicks that would pass for the app itself or address everyone, compared ignoring case.
+pub const RESERVED_NICKS: &[&str] = &[
+    "system", "server", "admin", "administrator", "moderator", "root", "everyone", "here", "yewchat",
+];
+
+/// Punctuation allowed besides letters and digits, never as the first character.
+const NICK_PUNCTUATION: &[char] = &['_', '-', '.'];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NickError {
+    Empty,
+    TooShort,
+    TooLong,
+    Whitespace,
+    /// Not a letter, digit or one of `_`, `-` and `.`; this covers control
+    /// characters and anything HTML-looking like `<`.
+    Disallowed(char),
+    /// Starts with punctuation rather than a letter or digit.
+    BadStart,
+    Reserved,
+}
+
+impl fmt::Display for NickError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NickError::Empty => write!(f, "Enter a nick"),
+            NickError::TooShort => write!(f, "Nicks are at least {} characters", MIN_NICK_LEN),
+            NickError::TooLong => write!(f, "Nicks are at most {} characters", MAX_NICK_LEN),
+            NickError::Whitespace => write!(f, "Nicks can't contain spaces"),
+            NickError::Disallowed(c) if c.is_control() => write!(f, "Nicks can't contain control characters"),
+            NickError::Disallowed(c) => write!(f, "\"{}\" isn't allowed; use letters, digits, _, - and .", c),
+            NickError::BadStart => write!(f, "Nicks start with a letter or digit"),
+            NickError::Reserved => write!(f, "That nick is reserved"),
+        }
+    }
+}
+
+impl std::error::Error for NickError {}
+
+/// The nick `name` registers as: trimmed, then checked against the rules above.
+/// Letters and digits from any script are fine; case is kept.
+pub fn validate_nick(name: &str) -> Result<String, NickError> {
+    let name = name.trim();
+    let first = name.chars().next().ok_or(NickError::Empty)?;
+    if let Some(c) = name.chars().find(|c| !c.is_alphanumeric() && !NICK_PUNCTUATION.contains(c)) {
+        return Err(if c.is_whitespace() { NickError::Whitespace } else { NickError::Disallowed(c) });
+    }
+    let len = name.chars().count();
+    if len < MIN_NICK_LEN {
+        return Err(NickError::TooShort);
+    }
+    if len > MAX_NICK_LEN {
+        return Err(NickError::TooLong);
+    }
+    if !first.is_alphanumeric() {
+        return Err(NickError::BadStart);
+    }
+    if RESERVED_NICKS.iter().any(|r| r.eq_ignore_ascii_case(name)) {
+        return Err(NickError::Reserved);
+    }
+    Ok(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_and_trims_nicks() {
+        assert_eq!(validate_nick("  alice "), Ok("alice".into()));
+        assert_eq!(validate_nick("Bob_2.0"), Ok("Bob_2.0".into()));
+        assert_eq!(validate_nick("Zoë"), Ok("Zoë".into()));
+        assert_eq!(validate_nick("名前"), Ok("名前".into()));
+        assert_eq!(validate_nick(&"a".repeat(MAX_NICK_LEN)), Ok("a".repeat(MAX_NICK_LEN)));
+    }
+
+    #[test]
+    fn rejects_bad_lengths() {
+        assert_eq!(validate_nick(""), Err(NickError::Empty));
+        assert_eq!(validate_nick("   "), Err(NickError::Empty));
+        assert_eq!(validate_nick("a"), Err(NickError::TooShort));
+        assert_eq!(validate_nick(&"a".repeat(500)), Err(NickError::TooLong));
+        // Counted in characters, not bytes.
+        assert_eq!(validate_nick(&"é".repeat(MAX_NICK_LEN)), Ok("é".repeat(MAX_NICK_LEN)));
+    }
+
+    #[test]
+    fn rejects_bad_characters() {
+        assert_eq!(validate_nick("two words"), Err(NickError::Whitespace));
+        assert_eq!(validate_nick("tab\there"), Err(NickError::Whitespace));
+        assert_eq!(validate_nick("bell\u{7}"), Err(NickError::Disallowed('\u{7}')));
+        assert_eq!(validate_nick("<b>hi</b>"), Err(NickError::Disallowed('<')));
+        assert_eq!(validate_nick("a@b"), Err(NickError::Disallowed('@')));
+        assert_eq!(validate_nick("_alice"), Err(NickError::BadStart));
+        assert_eq!(validate_nick(".."), Err(NickError::BadStart));
+    }
+
+    #[test]
+    fn rejects_reserved_nicks_in_any_case() {
+        assert_eq!(validate_nick("system"), Err(NickError::Reserved));
+        assert_eq!(validate_nick(" Admin "), Err(NickError::Reserved));
+        assert_eq!(validate_nick("systems"), Ok("systems".into()));
+    }
+}
@@ -0,0 +1,39 @@
+//! Room naming rules, shared so clients can reject a name before the server does.
+
+/// Room every registered user starts in.
+pub const DEFAULT_ROOM: &str = "global";
+
+pub const MAX_ROOM_LEN: usize = 32;
+
+/// Canonical form of a room name: trimmed, lowercase, a leading `#` dropped.
+///
+/// Returns `None` unless the result is 1..=[`MAX_ROOM_LEN`] characters of ASCII
+/// letters, digits, `-` and `_`, which keeps names safe to put in a URL path.
+pub fn normalize_room(name: &str) -> Option<String> {
+    let name = name.trim();
+    let name = name.strip_prefix('#').unwrap_or(name).to_ascii_lowercase();
+    let valid = !name.is_empty()
+        && name.len() <= MAX_ROOM_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_names() {
+        assert_eq!(normalize_room("  #Rust-Lang "), Some("rust-lang".into()));
+        assert_eq!(normalize_room(DEFAULT_ROOM), Some(DEFAULT_ROOM.into()));
+    }
+
+    #[test]
+    fn rejects_bad_names() {
+        assert_eq!(normalize_room(""), None);
+        assert_eq!(normalize_room("#"), None);
+        assert_eq!(normalize_room("two words"), None);
+        assert_eq!(normalize_room("../admin"), None);
+        assert_eq!(normalize_room(&"a".repeat(MAX_ROOM_LEN + 1)), None);
+    }
+}
@@ -0,0 +1,104 @@
+//! File uploads, sent as binary websocket messages next to the JSON frames.
+//!
+//! A client announces a file with [`ClientFrame::Upload`](crate::ClientFrame::Upload),
+//! then sends it in [`Chunk`]s of at most [`UPLOAD_CHUNK_BYTES`], waiting for each
+//! [`ServerFrame::UploadProgress`](crate::ServerFrame::UploadProgress) before the
+//! next. Finished files are posted as a message with an [`Attachment`] and served
+//! over plain HTTP under [`FILES_PATH`] on the websocket's host and port.
+
+use serde::{Deserialize, Serialize};
+
+/// Largest file the server accepts.
+pub const MAX_UPLOAD_BYTES: u64 = 8 * 1024 * 1024;
+/// Payload size of every chunk but the last.
+pub const UPLOAD_CHUNK_BYTES: u64 = 64 * 1024;
+/// Longest file name kept, in UTF-16 code units as JavaScript counts them.
+pub const MAX_FILE_NAME_LEN: usize = 255;
+/// HTTP path prefix uploaded files are served from, followed by the [`FileId`].
+pub const FILES_PATH: &str = "/files/";
+
+/// Chosen by the client, unique among its uploads on one connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct UploadId(pub u32);
+
+/// Assigned by the server once an upload completes. Random, so knowing one file's
+/// id says nothing about the others.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FileId(pub String);
+
+/// A file posted as (part of) a message.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: FileId,
+    pub name: String,
+    /// As reported by the uploader's browser; may be empty.
+    pub mime: String,
+    pub size: u64,
+}
+
+impl Attachment {
+    /// Where the file is served, relative to the server's origin.
+    pub fn path(&self) -> String {
+        format!("{}{}", FILES_PATH, self.id.0)
+    }
+}
+
+/// Bytes of the `upload id` and `index` prefix, both big-endian `u32`s.
+pub const CHUNK_HEADER_LEN: usize = 8;
+
+/// One binary websocket message: part `index` (from zero) of `upload`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    pub upload: UploadId,
+    pub index: u32,
+    pub data: Vec<u8>,
+}
+
+impl Chunk {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(CHUNK_HEADER_LEN + self.data.len());
+        out.extend_from_slice(&self.upload.0.to_be_bytes());
+        out.extend_from_slice(&self.index.to_be_bytes());
+        out.extend_from_slice(&self.data);
+        out
+    }
+
+    /// `None` if `raw` is too short to hold the header.
+    pub fn decode(raw: &[u8]) -> Option<Self> {
+        if raw.len() < CHUNK_HEADER_LEN {
+            return None;
+        }
+        let word = |at: usize| u32::from_be_bytes([raw[at], raw[at + 1], raw[at + 2], raw[at + 3]]);
+        Some(Chunk {
+            upload: UploadId(word(0)),
+            index: word(4),
+            data: raw[CHUNK_HEADER_LEN..].to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_round_trip() {
+        let chunk = Chunk {
+            upload: UploadId(7),
+            index: 3,
+            data: vec![1, 2, 3],
+        };
+        let raw = chunk.encode();
+        assert_eq!(raw, [0, 0, 0, 7, 0, 0, 0, 3, 1, 2, 3]);
+        assert_eq!(Chunk::decode(&raw), Some(chunk));
+    }
+
+    #[test]
+    fn empty_chunks_are_allowed_but_headers_are_not_optional() {
+        let header_only = Chunk::decode(&[0, 0, 0, 1, 0, 0, 0, 0]).unwrap();
+        assert!(header_only.data.is_empty());
+        assert_eq!(Chunk::decode(&[0, 0, 0, 1]), None);
+    }
+}
@@ -0,0 +1,78 @@
+use yewchat_protocol::{normalize_room, validate_nick};
+
+use super::{Action, Command, Registry};
+
+pub(super) fn register(registry: &mut Registry) {
+    let builtins: [(&'static str, &'static str, &'static str, super::Handler); 7] = [
+        ("nick", "<name>", "Change your nick, if nobody else has it", nick),
+        ("me", "<action>", "Describe what you are doing, as in \"* alice waves\"", me),
+        ("join", "<room>", "Open a room, creating it if nobody is in it", join),
+        ("leave", "[room]", "Leave a room, the current one by default", leave),
+        ("msg", "<user> <text>", "Send a direct message without switching to it", msg),
+        ("clear", "", "Clear this conversation's messages on screen", clear),
+        ("help", "[command]", "List the commands, or explain one", help),
+    ];
+    for (name, args, summary, handler) in builtins {
+        registry.register(Command {
+            name,
+            args,
+            summary,
+            handler,
+        });
+    }
+}
+
+fn nick(args: &str) -> Result<Action, String> {
+    match args {
+        "" => Err("Missing the new nick".into()),
+        name => validate_nick(name).map(Action::Nick).map_err(|e| e.to_string()),
+    }
+}
+
+fn me(args: &str) -> Result<Action, String> {
+    match args {
+        "" => Err("Missing the action".into()),
+        text => Ok(Action::Emote(text.to_string())),
+    }
+}
+
+fn room(name: &str) -> Result<String, String> {
+    normalize_room(name).ok_or_else(|| format!("\"{}\" is not a valid room name", name))
+}
+
+fn join(args: &str) -> Result<Action, String> {
+    match args {
+        "" => Err("Missing the room".into()),
+        name => room(name).map(Action::Join),
+    }
+}
+
+fn leave(args: &str) -> Result<Action, String> {
+    match args {
+        "" => Ok(Action::Leave(None)),
+        name => room(name).map(|room| Action::Leave(Some(room))),
+    }
+}
+
+fn msg(args: &str) -> Result<Action, String> {
+    let (user, text) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    match (user, text.trim()) {
+        ("", _) => Err("Missing the user".into()),
+        (_, "") => Err("Missing the message".into()),
+        (user, text) => Ok(Action::Msg {
+            user: user.to_string(),
+            text: text.to_string(),
+        }),
+    }
+}
+
+fn clear(args: &str) -> Result<Action, String> {
+    match args {
+        "" => Ok(Action::Clear),
+        _ => Err("/clear takes no arguments".into()),
+    }
+}
+
+fn help(args: &str) -> Result<Action, String> {
+    Ok(Action::Help(Some(args.to_string()).filter(|a| !a.is_empty())))
+}
@@ -0,0 +1,20 @@
+//! Commands specific to this deployment. Anything registered here is available
+//! in every chat and listed by `/help`; registering a built-in's name replaces it.
+
+use super::{Action, Command, Registry};
+
+pub(super) fn register(registry: &mut Registry) {
+    registry.register(Command {
+        name: "code",
+        args: "<text>",
+        summary: "Send text as a code block",
+        handler: code,
+    });
+}
+
+fn code(args: &str) -> Result<Action, String> {
+    match args {
+        "" => Err("Missing the code".into()),
+        code => Ok(Action::Send(format!("```\n{}\n```", code))),
+    }
+}
@@ -0,0 +1,191 @@
+//! Slash commands typed into the chat input, like `/join rust` or `/msg bob hi`.
+//!
+//! A [`Registry`] turns a line of input into an [`Action`] for the chat to carry
+//! out. Built-in commands are in `builtin.rs`; deployment-specific ones go in
+//! `custom.rs` and are registered the same way.
+
+mod builtin;
+mod custom;
+
+/// What a command asks the chat to do.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Post `text` to the current conversation.
+    Send(String),
+    /// Post `text` to the current conversation as a `/me` action.
+    Emote(String),
+    /// Ask the server to rename us.
+    Nick(String),
+    /// Open a room, joining it if needed.
+    Join(String),
+    /// Leave the named room, or the current one.
+    Leave(Option<String>),
+    /// Send `text` privately to `user` without leaving the current conversation.
+    Msg { user: String, text: String },
+    /// Empty the current conversation's message list in this tab.
+    Clear,
+    /// List every command, or describe one.
+    Help(Option<String>),
+}
+
+/// Turns what follows the command name into an [`Action`], or says what is wrong with it.
+pub type Handler = fn(args: &str) -> Result<Action, String>;
+
+#[derive(Clone, Debug)]
+pub struct Command {
+    /// Typed after the `/`, in lowercase.
+    pub name: &'static str,
+    /// The arguments as shown in `/help` and usage errors, e.g. `<user> <text>`.
+    pub args: &'static str,
+    pub summary: &'static str,
+    pub handler: Handler,
+}
+
+impl Command {
+    pub fn usage(&self) -> String {
+        if self.args.is_empty() {
+            format!("/{}", self.name)
+        } else {
+            format!("/{} {}", self.name, self.args)
+        }
+    }
+}
+
+pub struct Registry {
+    /// Sorted by name.
+    commands: Vec<Command>,
+}
+
+impl Default for Registry {
+    /// The built-in commands plus the custom ones.
+    fn default() -> Self {
+        let mut registry = Registry::builtin();
+        custom::register(&mut registry);
+        registry
+    }
+}
+
+impl Registry {
+    pub fn builtin() -> Self {
+        let mut registry = Registry { commands: vec![] };
+        builtin::register(&mut registry);
+        registry
+    }
+
+    /// Adds `command`, replacing any command with the same name.
+    pub fn register(&mut self, command: Command) {
+        self.commands.retain(|c| c.name != command.name);
+        self.commands.push(command);
+        self.commands.sort_by_key(|c| c.name);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Command> {
+        self.commands.iter().find(|c| c.name == name)
+    }
+
+    /// `None` unless `input` starts with `/`. A leading `//` sends the rest as an
+    /// ordinary message that starts with `/`.
+    pub fn parse(&self, input: &str) -> Option<Result<Action, String>> {
+        let rest = input.trim().strip_prefix('/')?;
+        if rest.starts_with('/') {
+            return Some(Ok(Action::Send(rest.to_string())));
+        }
+        let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let name = name.to_lowercase();
+        Some(match self.get(&name) {
+            Some(command) => {
+                (command.handler)(args.trim()).map_err(|reason| format!("{}. Usage: {}", reason, command.usage()))
+            }
+            None => Err(format!("Unknown command /{}. Type /help to list them", name)),
+        })
+    }
+
+    /// Lines for `/help`: every command with its summary, or the usage of `name`.
+    pub fn help(&self, name: Option<&str>) -> Result<Vec<String>, String> {
+        match name {
+            Some(name) => {
+                let name = name.trim_start_matches('/').to_lowercase();
+                let command = self.get(&name).ok_or_else(|| format!("There is no /{} command", name))?;
+                Ok(vec![command.usage(), command.summary.to_string()])
+            }
+            None => Ok(self
+                .commands
+                .iter()
+                .map(|c| format!("{} - {}", c.usage(), c.summary))
+                .chain(["Start a message with // to send it with a leading /".to_string()])
+                .collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(input: &str) -> Option<Result<Action, String>> {
+        Registry::default().parse(input)
+    }
+
+    #[test]
+    fn plain_text_is_not_a_command() {
+        assert_eq!(run("hello /join"), None);
+        assert_eq!(run("//join is a command"), Some(Ok(Action::Send("/join is a command".into()))));
+    }
+
+    #[test]
+    fn parses_builtins() {
+        assert_eq!(run("/join #Rust"), Some(Ok(Action::Join("rust".into()))));
+        assert_eq!(run("/LEAVE"), Some(Ok(Action::Leave(None))));
+        assert_eq!(run("/leave rust"), Some(Ok(Action::Leave(Some("rust".into())))));
+        assert_eq!(run("/me  waves "), Some(Ok(Action::Emote("waves".into()))));
+        assert_eq!(run("/nick bob"), Some(Ok(Action::Nick("bob".into()))));
+        assert_eq!(run("/clear"), Some(Ok(Action::Clear)));
+        assert_eq!(run("/help /msg"), Some(Ok(Action::Help(Some("/msg".into())))));
+        assert_eq!(
+            run("/msg bob see you  later"),
+            Some(Ok(Action::Msg {
+                user: "bob".into(),
+                text: "see you  later".into(),
+            }))
+        );
+    }
+
+    #[test]
+    fn reports_usage_errors() {
+        assert_eq!(run("/msg bob"), Some(Err("Missing the message. Usage: /msg <user> <text>".into())));
+        assert_eq!(run("/join no way"), Some(Err("\"no way\" is not a valid room name. Usage: /join <room>".into())));
+        assert_eq!(run("/nick"), Some(Err("Missing the new nick. Usage: /nick <name>".into())));
+        assert_eq!(run("/nick system"), Some(Err("That nick is reserved. Usage: /nick <name>".into())));
+        assert_eq!(run("/clear now"), Some(Err("/clear takes no arguments. Usage: /clear".into())));
+        assert_eq!(run("/dance"), Some(Err("Unknown command /dance. Type /help to list them".into())));
+    }
+
+    #[test]
+    fn custom_commands_can_be_added_and_override_builtins() {
+        fn shout(args: &str) -> Result<Action, String> {
+            Ok(Action::Send(args.to_uppercase()))
+        }
+        let mut registry = Registry::builtin();
+        for name in ["shout", "me"] {
+            registry.register(Command {
+                name,
+                args: "<text>",
+                summary: "Says it loudly",
+                handler: shout,
+            });
+        }
+        assert_eq!(registry.parse("/shout hi"), Some(Ok(Action::Send("HI".into()))));
+        assert_eq!(registry.parse("/me hi"), Some(Ok(Action::Send("HI".into()))));
+        assert_eq!(registry.help(Some("shout")), Ok(vec!["/shout <text>".into(), "Says it loudly".into()]));
+    }
+
+    #[test]
+    fn help_lists_every_command() {
+        let registry = Registry::default();
+        let lines = registry.help(None).unwrap();
+        for name in ["nick", "me", "join", "leave", "clear", "help", "msg", "code"] {
+            assert!(lines.iter().any(|l| l.starts_with(&format!("/{}", name))), "/{} missing", name);
+        }
+        assert!(registry.help(Some("dance")).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use web_sys::HtmlInputElement;
+use yew::functional::*;
+use yew::prelude::*;
+use yew_router::prelude::*;
+use yewchat_protocol::{normalize_room, Channel, DEFAULT_ROOM};
+
+use crate::Route;
+
+#[derive(Clone, PartialEq)]
+pub struct ChannelEntry {
+    pub channel: Channel,
+    pub unread: usize,
+    /// Unread messages that mention us.
+    pub mentions: usize,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ChannelListProps {
+    /// Rooms this client is in and direct conversations it has had.
+    pub channels: Vec<ChannelEntry>,
+    /// Rooms other people are in, from the server's `rooms` broadcast.
+    pub available: Vec<String>,
+    pub active: Channel,
+    pub on_leave: Callback<String>,
+}
+
+pub fn channel_route(channel: &Channel) -> Route {
+    match channel {
+        Channel::Room(room) if room == DEFAULT_ROOM => Route::Chat,
+        Channel::Room(room) => Route::Room { id: room.clone() },
+        Channel::Direct(user) => Route::Direct { user: user.clone() },
+    }
+}
+
+pub fn channel_label(channel: &Channel) -> String {
+    match channel {
+        Channel::Room(room) => format!("#{}", room),
+        Channel::Direct(user) => format!("@{}", user),
+    }
+}
+
+/// Joined rooms and direct conversations with unread counts, other open rooms,
+/// and a box to create or join a room by name.
+#[function_component(ChannelList)]
+pub fn channel_list(props: &ChannelListProps) -> Html {
+    let history = use_history().expect("ChannelList rendered outside a router");
+    let name = use_state(String::new);
+    let error = use_state(|| false);
+
+    let oninput = {
+        let name = name.clone();
+        let error = error.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            name.set(input.value());
+            error.set(false);
+        })
+    };
+
+    let onsubmit = {
+        let name = name.clone();
+        let error = error.clone();
+        Callback::from(move |e: FocusEvent| {
+            e.prevent_default();
+            match normalize_room(&name) {
+                Some(room) => {
+                    history.push(channel_route(&Channel::Room(room)));
+                    name.set(String::new());
+                }
+                None => error.set(true),
+            }
+        })
+    };
+
+    let entry = |entry: &ChannelEntry| {
+        let is_active = entry.channel == props.active;
+        let leave = match &entry.channel {
+            Channel::Room(room) if room != DEFAULT_ROOM => {
+                let on_leave = props.on_leave.clone();
+                let room = room.clone();
+                Some(Callback::from(move |e: MouseEvent| {
+                    e.prevent_default();
+                    e.stop_propagation();
+                    on_leave.emit(room.clone());
+                }))
+            }
+            _ => None,
+        };
+        let bg = if is_active { "bg-purple-500/30 text-white" } else { "text-gray-300 hover:bg-white/10" };
+        html! {
+            <Link<Route> to={channel_route(&entry.channel)} classes={classes!("group", "flex", "items-center", "justify-between", "rounded-lg", "px-3", "py-2", "transition-colors", bg)}>
+                <span class="font-medium">{channel_label(&entry.channel)}</span>
+                <span class="flex items-center space-x-2">
+                    if entry.mentions > 0 && !is_active {
+                        <span title="Mentions" class="px-2 py-0.5 text-xs rounded-full bg-amber-500 text-gray-900 font-semibold">{format!("@{}", entry.mentions)}</span>
+                    }
+                    if entry.unread > 0 && !is_active {
+                        <span class="px-2 py-0.5 text-xs rounded-full bg-purple-500 text-white">{entry.unread}</span>
+                    }
+                    if let Some(onclick) = leave {
+                        <button {onclick} title="Leave room" class="text-gray-500 hover:text-red-300 opacity-0 group-hover:opacity-100">{"×"}</button>
+                    }
+                </span>
+            </Link<Route>>
+        }
+    };
+
+    let (rooms, directs): (Vec<&ChannelEntry>, Vec<&ChannelEntry>) = props
+        .channels
+        .iter()
+        .partition(|e| matches!(e.channel, Channel::Room(_)));
+    let others: Vec<&String> = props
+        .available
+        .iter()
+        .filter(|r| !rooms.iter().any(|e| e.channel == Channel::Room((*r).clone())))
+        .collect();
+
+    html! {
+        <div class="p-4 border-b border-white/10 space-y-2">
+            <h3 class="text-xs font-semibold uppercase tracking-wide text-gray-400">{"Rooms"}</h3>
+            { rooms.into_iter().map(entry).collect::<Html>() }
+            {
+                others.into_iter().map(|room| html! {
+                    <Link<Route> to={channel_route(&Channel::Room(room.clone()))} classes="flex items-center rounded-lg px-3 py-2 text-gray-500 hover:bg-white/10 hover:text-gray-300 transition-colors">
+                        {format!("#{}", room)}
+                    </Link<Route>>
+                }).collect::<Html>()
+            }
+            <form {onsubmit} class="pt-2">
+                <input
+                    {oninput}
+                    value={(*name).clone()}
+                    class="w-full px-3 py-2 bg-white/10 border border-white/20 rounded-lg text-sm text-white placeholder-gray-400 focus:outline-none focus:ring-2 focus:ring-purple-500"
+                    placeholder="Create or join a room"
+                    type="text"
+                />
+                if *error {
+                    <p class="mt-1 text-xs text-red-300">{"Use letters, digits, - and _ only."}</p>
+                }
+            </form>
+            if !directs.is_empty() {
+                <h3 class="pt-2 text-xs font-semibold uppercase tracking-wide text-gray-400">{"Direct messages"}</h3>
+                { directs.into_iter().map(entry).collect::<Html>() }
+            }
+        </div>
+    }
+}
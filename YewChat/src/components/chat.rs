@@ -1,54 +1,541 @@
-use serde::{Deserialize, Serialize};
-use web_sys::HtmlInputElement;
+use std::collections::{BTreeMap, HashMap};
+
+use web_sys::{DataTransfer, Element, FileList, HtmlInputElement, HtmlTextAreaElement};
+use gloo_file::File;
+use gloo_timers::callback::{Interval, Timeout};
+use js_sys::Date;
+use wasm_bindgen::JsCast;
 use yew::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+use yew::html::Scope;
 use yew_agent::{Bridge, Bridged};
+use yew_router::prelude::*;
+use yewchat_protocol::{
+    AuthorId, Channel, ChatMessage, Chunk, ClientFrame, ClientId, MessageId, RegisterStatus, ServerFrame, Timestamp, UploadId,
+    DEFAULT_ROOM, HISTORY_PAGE_SIZE, MAX_UPLOAD_BYTES, TYPING_REFRESH_MS, TYPING_TIMEOUT_MS, UPLOAD_CHUNK_BYTES,
+};
 
-use crate::{services::{event_bus::EventBus, websocket::WebsocketService}, User};
+use crate::{
+    commands::{Action, Registry},
+    components::{
+        channel_list::{channel_label, channel_route, ChannelEntry, ChannelList},
+        composer::Composer,
+        diagnostics::{self, Diagnostic, Diagnostics, Severity},
+        message::{format_size, Delivery, MessageBubble},
+        thread::ThreadPanel,
+        timestamp::{millis, same_day, DaySeparator},
+    },
+    directory::{avatar_url, Directory},
+    mention,
+    services::{
+        connection::{ConnectionBus, ConnectionState},
+        endpoint,
+        event_bus::EventBus,
+        history, session,
+        websocket::{Handoff, WebsocketService},
+    },
+    Route, User,
+};
 
+#[allow(clippy::enum_variant_names)]
 pub enum Msg {
     HandleMsg(String),
-    SubmitMessage,
+    ConnectionChanged(ConnectionState),
+    /// Trimmed and non-empty, from the [`Composer`].
+    SubmitMessage(String),
+    EditMessage(MessageId, String),
+    DeleteMessage(MessageId),
+    React(MessageId, String, bool),
+    OpenThread(MessageId),
+    CloseThread,
+    SubmitReply(String),
+    Typed,
+    StopTyping,
+    ExpireTyping,
+    LeaveRoom(String),
+    OpenDirect(String),
+    Hydrated(Channel, Vec<ChatMessage>),
+    LoadOlder,
+    ClearHistory,
+    ClearDiagnostics,
+    Tick,
+    /// Files dropped, pasted or picked to be sent to the current conversation.
+    Attach(Vec<web_sys::File>),
+    ChunkRead(UploadId, Result<Vec<u8>, String>),
+    DismissUpload(UploadId),
+    Dragging(bool),
+    /// Moves the highlighted autocomplete suggestion up (-1) or down (1).
+    MentionMove(i32),
+    /// Completes the `@` being typed with this name, or with the highlighted one.
+    MentionPick(Option<String>),
+    MentionClose,
+    /// Opens the first conversation with unread mentions.
+    JumpToMention,
+    DismissNotice,
+    /// The server never answered this message.
+    AckTimeout(ClientId),
+    Resend(ClientId),
+    Discard(ClientId),
+    /// Forgets the nick and goes back to the login screen.
+    Logout,
 }
 
-#[derive(Deserialize)]
-struct MessageData {
-    from: String,
-    message: String,
+#[derive(Properties, PartialEq)]
+pub struct ChatProps {
+    pub channel: Channel,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum MsgTypes {
-    Users,
-    Register,
-    Message,
+/// Everything we hold for one joined room or direct conversation.
+#[derive(Default)]
+struct Conversation {
+    messages: Vec<ChatMessage>,
+    members: Vec<String>,
+    unread: usize,
+    /// Unread messages that mention us.
+    mentions: usize,
+    /// A page of older messages has been asked for and not answered yet.
+    loading_older: bool,
+    /// The server has nothing older than our first message.
+    complete: bool,
+    /// Who is typing here, and when their indicator lapses if not refreshed.
+    typing: BTreeMap<String, f64>,
 }
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct WebSocketMessage {
-    message_type: MsgTypes,
-    data_array: Option<Vec<String>>,
-    data: Option<String>,
+impl Conversation {
+    /// Adds the messages we don't hold yet, keeping id order, and returns them.
+    fn merge(&mut self, incoming: Vec<ChatMessage>) -> Vec<ChatMessage> {
+        let added: Vec<ChatMessage> = incoming
+            .into_iter()
+            .filter(|m| !self.messages.iter().any(|held| held.id == m.id))
+            .collect();
+        self.messages.extend(added.iter().cloned());
+        self.messages.sort_by_key(|m| m.id);
+        added
+    }
+
+    /// Swaps in an edited or deleted copy of a message we hold.
+    fn replace(&mut self, message: ChatMessage) -> bool {
+        match self.messages.iter_mut().find(|held| held.id == message.id) {
+            Some(held) => {
+                *held = message;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn oldest(&self) -> Option<MessageId> {
+        self.messages.first().map(|m| m.id)
+    }
+}
+
+/// Feedback on the last slash command, shown above the input.
+enum Notice {
+    /// Goes away once the input is edited.
+    Error(String),
+    /// Stays until dismissed, like the `/help` listing.
+    Info(Vec<String>),
+}
+
+/// A message we sent that the server hasn't echoed back yet. Kept across
+/// reconnects and sent again once the connection is back.
+struct Outgoing {
+    client_id: ClientId,
+    channel: Channel,
+    text: String,
+    reply_to: Option<MessageId>,
+    mentions: Vec<String>,
+    emote: bool,
+    time: f64,
+    /// Why it didn't go through. Failed messages stay until retried or discarded.
+    failed: Option<String>,
+    /// Marks the message failed if no echo arrives in time. Only runs while connected.
+    ack_timeout: Option<Timeout>,
+}
+
+/// What each bubble needs to know about the rest of its conversation, worked out
+/// once per render rather than by scanning the conversation for every message.
+struct Timeline<'a> {
+    by_id: HashMap<MessageId, &'a ChatMessage>,
+    replies: HashMap<MessageId, usize>,
+    /// Our latest acknowledged message, the only one marked delivered.
+    delivered: Option<MessageId>,
+}
+
+impl<'a> Timeline<'a> {
+    fn new(messages: &'a [ChatMessage], me: &str, author: Option<&AuthorId>) -> Self {
+        let mut replies = HashMap::new();
+        for root in messages.iter().filter_map(|m| m.reply_to) {
+            *replies.entry(root).or_default() += 1;
+        }
+        Timeline {
+            by_id: messages.iter().map(|m| (m.id, m)).collect(),
+            replies,
+            delivered: messages.iter().rev().find(|m| m.is_from(me, author) && m.client_id.is_some()).map(|m| m.id),
+        }
+    }
 }
 
-#[derive(Clone)]
-struct UserProfile {
-    name: String,
-    avatar: String,
+/// A file on its way to the server. Each chunk is read from disk only once the
+/// server has acknowledged the one before it.
+struct Upload {
+    id: UploadId,
+    channel: Channel,
+    file: File,
+    /// Bytes the server has acknowledged.
+    received: u64,
+    /// Why the upload stopped. Failed uploads stay listed until dismissed.
+    failed: Option<String>,
 }
 
+/// How often relative timestamps ("2 min ago") are refreshed.
+const CLOCK_INTERVAL_MS: u32 = 30_000;
+/// We announce we've stopped typing after this long without a keystroke.
+const TYPING_IDLE_MS: u32 = 4_000;
+/// How often lapsed typing indicators are swept.
+const TYPING_SWEEP_MS: u32 = 1_000;
+/// Names offered when completing an `@` mention.
+const MAX_SUGGESTIONS: usize = 6;
+/// How long a sent message may go unacknowledged on an open connection.
+const ACK_TIMEOUT_MS: u32 = 10_000;
+/// Older messages are requested once the message list is scrolled this close to the top.
+const LOAD_OLDER_THRESHOLD_PX: i32 = 48;
+
 pub struct Chat {
-    users: Vec<UserProfile>,
+    directory: Directory,
     chat_input: NodeRef,
+    messages_ref: NodeRef,
+    /// Scroll height and offset from before older messages were prepended, so the
+    /// view can stay on the same message once they render.
+    scroll_anchor: Option<(i32, i32)>,
+    /// Where and when we last sent `typing: true`.
+    typing_sent: Option<(Channel, f64)>,
+    typing_idle: Option<Timeout>,
+    /// The thread open in the side panel, by the id of the message that started it.
+    thread: Option<MessageId>,
+    uploads: Vec<Upload>,
+    next_upload: u32,
+    outbox: Vec<Outgoing>,
+    next_client_id: u32,
+    /// Where attachments are downloaded from, resolved once for the whole chat.
+    files_base: String,
+    /// Files are being dragged over the chat area.
+    dragging: bool,
+    /// The `@` mention being typed: the byte offset of its `@` and the name so far.
+    mention: Option<(usize, String)>,
+    /// Which autocomplete suggestion is highlighted.
+    mention_selected: usize,
+    commands: Registry,
+    notice: Option<Notice>,
+    /// The nick asked for with `/nick`, until the server answers.
+    renaming: Option<String>,
     wss: WebsocketService,
-    messages: Vec<MessageData>,
+    conversations: BTreeMap<Channel, Conversation>,
+    available_rooms: Vec<String>,
+    connection: ConnectionState,
+    diagnostics: Vec<Diagnostic>,
+    now: f64,
+    _clock: Interval,
+    _typing_sweep: Interval,
     _producer: Box<dyn Bridge<EventBus>>,
+    _connection: Box<dyn Bridge<ConnectionBus>>,
+}
+
+impl Chat {
+    fn username(&self, ctx: &Context<Self>) -> String {
+        let (user, _) = ctx.link().context::<User>(Callback::noop()).expect("Context to be set");
+        let username = user.username.borrow().clone();
+        username
+    }
+
+    fn author(&self, ctx: &Context<Self>) -> Option<AuthorId> {
+        let (user, _) = ctx.link().context::<User>(Callback::noop()).expect("Context to be set");
+        let author = user.author.borrow().clone();
+        author
+    }
+
+    /// Asks the server for a page of `channel` before `before`, or its latest page.
+    fn request_history(&self, channel: &Channel, before: Option<MessageId>) {
+        self.wss.send(&ClientFrame::History {
+            channel: channel.clone(),
+            before,
+            limit: HISTORY_PAGE_SIZE,
+        });
+    }
+
+    /// One message bubble, wired up to this component. `in_thread` drops the quote
+    /// and reply links, which are redundant inside the thread panel.
+    fn bubble(&self, ctx: &Context<Self>, m: &ChatMessage, timeline: &Timeline, in_thread: bool) -> Html {
+        let me = self.username(ctx);
+        let is_own = m.is_from(&me, self.author(ctx).as_ref());
+        // Senders stay in the directory after leaving, so history keeps rendering.
+        let sender = self.directory.get(&m.from);
+        let avatar = sender.map(|u| u.avatar.clone()).unwrap_or_else(|| avatar_url(&m.from));
+        let online = sender.is_some_and(|u| u.online);
+        let on_edit = ctx.link().callback(|(id, text)| Msg::EditMessage(id, text));
+        let on_delete = ctx.link().callback(Msg::DeleteMessage);
+        let on_react = ctx.link().callback(|(id, emoji, add)| Msg::React(id, emoji, add));
+        let parent = m.reply_to.and_then(|id| timeline.by_id.get(&id).map(|&p| p.clone()));
+        let replies = timeline.replies.get(&m.id).copied().unwrap_or_default();
+        let on_thread = (!in_thread).then(|| ctx.link().callback(Msg::OpenThread));
+        // Only our latest acknowledged message says so, like most messengers.
+        let delivered = timeline.delivered == Some(m.id);
+
+        html! {
+            <MessageBubble
+                key={m.id.0.to_string()}
+                delivery={delivered.then_some(Delivery::Delivered)}
+                message={m.clone()}
+                {is_own}
+                {me}
+                {avatar}
+                {online}
+                now={self.now}
+                {on_edit}
+                {on_delete}
+                {on_react}
+                {parent}
+                {replies}
+                {on_thread}
+                files_base={self.files_base.clone()}
+            />
+        }
+    }
+
+    /// A message from the outbox, drawn like the real one will be.
+    fn pending_bubble(&self, ctx: &Context<Self>, out: &Outgoing) -> Html {
+        let me = self.username(ctx);
+        let message = ChatMessage {
+            // Never shown and never matches a real message.
+            id: MessageId(u64::MAX),
+            channel: out.channel.clone(),
+            from: me.clone(),
+            text: out.text.clone(),
+            time: Timestamp(out.time as u64),
+            reply_to: out.reply_to,
+            edited: None,
+            deleted: false,
+            reactions: BTreeMap::new(),
+            mentions: out.mentions.clone(),
+            emote: out.emote,
+            attachment: None,
+            client_id: Some(out.client_id.clone()),
+            author: self.author(ctx),
+        };
+        let delivery = match &out.failed {
+            Some(reason) => Delivery::Failed(reason.clone()),
+            None => Delivery::Sending,
+        };
+        let (retry, discard) = (out.client_id.clone(), out.client_id.clone());
+
+        html! {
+            <MessageBubble
+                key={out.client_id.0.clone()}
+                {message}
+                is_own=true
+                avatar={self.directory.get(&me).map(|u| u.avatar.clone()).unwrap_or_else(|| avatar_url(&me))}
+                online={self.connection.is_open()}
+                {me}
+                now={self.now}
+                on_edit={Callback::noop()}
+                on_delete={Callback::noop()}
+                on_react={Callback::noop()}
+                delivery={Some(delivery)}
+                on_retry={ctx.link().callback(move |_| Msg::Resend(retry.clone()))}
+                on_discard={ctx.link().callback(move |_| Msg::Discard(discard.clone()))}
+            />
+        }
+    }
+
+    /// Names that complete the `@` being typed: people in this conversation
+    /// first, then everyone else seen this session.
+    fn mention_suggestions(&self, ctx: &Context<Self>) -> Vec<String> {
+        let Some((_, prefix)) = &self.mention else { return vec![] };
+        let me = self.username(ctx);
+        let channel = &ctx.props().channel;
+        let mut names: Vec<String> = match channel {
+            Channel::Room(_) => self.conversations.get(channel).map(|c| c.members.clone()).unwrap_or_default(),
+            Channel::Direct(peer) => vec![peer.clone()],
+        };
+        names.extend(self.directory.names());
+        mention::suggestions(prefix, names.iter().map(String::as_str).filter(|n| *n != me), MAX_SUGGESTIONS)
+    }
+
+    /// The known nicks `text` mentions, to send along with it.
+    fn mentions_in(&self, text: &str) -> Vec<String> {
+        mention::mentioned(text, &self.directory.names())
+    }
+
+    /// Brings a fresh connection up to date: it only knows about the default room,
+    /// and anything unanswered may have been lost with the old one.
+    fn resync(&mut self, ctx: &Context<Self>) {
+        for channel in self.conversations.keys() {
+            match channel {
+                Channel::Room(room) if room != DEFAULT_ROOM => {
+                    self.wss.send(&ClientFrame::Join { room: room.clone() });
+                }
+                _ => {}
+            }
+            // Catch up on anything posted while we were away; the reply pages back
+            // further if one page doesn't cover it.
+            self.request_history(channel, None);
+        }
+        let waiting: Vec<ClientId> =
+            self.outbox.iter().filter(|o| o.failed.is_none()).map(|o| o.client_id.clone()).collect();
+        for client_id in waiting {
+            self.transmit(ctx, &client_id);
+        }
+    }
+
+    /// Shows the message as sending right away and hands it to the connection.
+    fn post(&mut self, ctx: &Context<Self>, channel: Channel, text: String, reply_to: Option<MessageId>, emote: bool) {
+        self.next_client_id = self.next_client_id.wrapping_add(1);
+        // The time keeps ids from an earlier page load apart.
+        let client_id = ClientId(format!("{:x}-{}", Date::now() as u64, self.next_client_id));
+        self.outbox.push(Outgoing {
+            client_id: client_id.clone(),
+            channel,
+            mentions: self.mentions_in(&text),
+            text,
+            reply_to,
+            emote,
+            time: Date::now(),
+            failed: None,
+            ack_timeout: None,
+        });
+        self.transmit(ctx, &client_id);
+    }
+
+    /// (Re)sends a message from the outbox. The server ignores repeats of a
+    /// message it already stored, answering with the stored copy instead.
+    fn transmit(&mut self, ctx: &Context<Self>, client_id: &ClientId) {
+        let Some(out) = self.outbox.iter_mut().find(|o| o.client_id == *client_id) else {
+            return;
+        };
+        out.failed = None;
+        let sent = self.wss.try_send(&ClientFrame::Message {
+            channel: out.channel.clone(),
+            text: out.text.clone(),
+            reply_to: out.reply_to,
+            mentions: out.mentions.clone(),
+            emote: out.emote,
+            client_id: Some(client_id.clone()),
+        });
+        if let Err(e) = sent {
+            out.failed = Some(e);
+            out.ack_timeout = None;
+            return;
+        }
+        // While offline the message waits in the connection's queue instead.
+        out.ack_timeout = self.connection.is_open().then(|| {
+            let link = ctx.link().clone();
+            let client_id = client_id.clone();
+            Timeout::new(ACK_TIMEOUT_MS, move || link.send_message(Msg::AckTimeout(client_id)))
+        });
+    }
+
+    /// Carries out a slash command typed into the input.
+    fn run(&mut self, ctx: &Context<Self>, action: Action) {
+        let channel = ctx.props().channel.clone();
+        match action {
+            Action::Send(text) => self.post(ctx, channel, text, None, false),
+            Action::Emote(text) => self.post(ctx, channel, text, None, true),
+            Action::Msg { user, text } => self.post(ctx, Channel::Direct(user), text, None, false),
+            Action::Join(room) => {
+                if let Some(history) = ctx.link().history() {
+                    history.push(channel_route(&Channel::Room(room)));
+                }
+            }
+            Action::Leave(room) => match room.or(match channel {
+                Channel::Room(current) => Some(current),
+                Channel::Direct(_) => None,
+            }) {
+                Some(room) if room == DEFAULT_ROOM => {
+                    self.notice = Some(Notice::Error(format!("Everyone stays in #{}", DEFAULT_ROOM)));
+                }
+                Some(room) => ctx.link().send_message(Msg::LeaveRoom(room)),
+                None => self.notice = Some(Notice::Error("This isn't a room; name the one to leave".into())),
+            },
+            Action::Nick(nick) => {
+                // Answered with a `RegisterResult`; the nick only changes once it's accepted.
+                self.wss.send(&ClientFrame::register(nick.clone(), &session::token()));
+                self.renaming = Some(nick);
+            }
+            Action::Clear => {
+                if let Some(conversation) = self.conversations.get_mut(&channel) {
+                    conversation.messages.clear();
+                    conversation.complete = false;
+                }
+                self.thread = None;
+            }
+            Action::Help(name) => {
+                self.notice = Some(match self.commands.help(name.as_deref()) {
+                    Ok(lines) => Notice::Info(lines),
+                    Err(e) => Notice::Error(e),
+                });
+            }
+        }
+    }
+
+    fn stop_typing(&mut self) {
+        self.typing_idle = None;
+        if let Some((channel, _)) = self.typing_sent.take() {
+            self.wss.send(&ClientFrame::Typing { channel, typing: false });
+        }
+    }
+}
+
+/// "alice is typing...", collapsing once more than two people are at it.
+fn typing_label(names: &[&String]) -> Option<String> {
+    match names {
+        [] => None,
+        [one] => Some(format!("{} is typing...", one)),
+        [one, two] => Some(format!("{} and {} are typing...", one, two)),
+        _ => Some("Several people are typing...".into()),
+    }
+}
+
+/// Stores `messages` in this browser's history without waiting for it.
+fn persist(owner: String, channel: Channel, messages: Vec<ChatMessage>) {
+    if messages.is_empty() {
+        return;
+    }
+    spawn_local(async move {
+        if let Err(e) = history::append(&owner, &channel, &messages).await {
+            log::warn!("Could not store messages locally: {}", e);
+        }
+    });
+}
+
+/// Reads the chunk of `file` starting at `offset` and hands it back as [`Msg::ChunkRead`].
+fn read_chunk(link: &Scope<Chat>, upload: UploadId, file: &File, offset: u64) {
+    let link = link.clone();
+    let chunk = file.slice(offset, (offset + UPLOAD_CHUNK_BYTES).min(file.size()));
+    spawn_local(async move {
+        let read = gloo_file::futures::read_as_bytes(&chunk).await.map_err(|e| e.to_string());
+        link.send_message(Msg::ChunkRead(upload, read));
+    });
+}
+
+fn files(list: Option<FileList>) -> Vec<web_sys::File> {
+    list.map(|list| (0..list.length()).filter_map(|i| list.get(i)).collect())
+        .unwrap_or_default()
+}
+
+/// Loads what this browser stored for `channel` and hands it back as [`Msg::Hydrated`].
+fn hydrate(link: &Scope<Chat>, owner: String, channel: Channel) {
+    let link = link.clone();
+    spawn_local(async move {
+        match history::load(&owner, &channel).await {
+            Ok(messages) => link.send_message(Msg::Hydrated(channel, messages)),
+            Err(e) => log::warn!("Could not load local history for {:?}: {}", channel, e),
+        }
+    });
 }
 
 impl Component for Chat {
     type Message = Msg;
-    type Properties = ();
+    type Properties = ChatProps;
 
     fn create(ctx: &Context<Self>) -> Self {
         let (user, _) = ctx
@@ -56,82 +543,768 @@ impl Component for Chat {
             .context::<User>(Callback::noop())
             .expect("Context to be set");
 
-        let wss = WebsocketService::new();
         let username = user.username.borrow().clone();
 
-        let message = WebSocketMessage {
-            message_type: MsgTypes::Register,
-            data: Some(username.to_string()),
-            data_array: None,
+        log::debug!("Create function");
+
+        // The login screen hands over the connection it registered. It's been taking
+        // frames since, so registering again has the server repeat the users and rooms.
+        let (handoff, _) = ctx.link().context::<Handoff>(Callback::noop()).expect("Context to be set");
+        let wss = match handoff.take() {
+            Some(wss) => {
+                wss.send(&ClientFrame::register(username.clone(), &session::token()));
+                wss
+            }
+            // The service re-sends this on every reconnect.
+            None => WebsocketService::new(ClientFrame::register(username.clone(), &session::token())),
         };
 
-        log::debug!("Create function");
+        spawn_local(async {
+            if let Err(e) = history::expire().await {
+                log::warn!("Could not expire local history: {}", e);
+            }
+        });
 
-        if let Ok(_) = wss.tx.clone().try_send(serde_json::to_string(&message).unwrap()) {
-            log::debug!("Message sent successfully!");
+        // The server puts everyone in the default room on register.
+        let mut conversations = BTreeMap::new();
+        conversations.insert(Channel::Room(DEFAULT_ROOM.into()), Conversation::default());
+        let channel = ctx.props().channel.clone();
+        match &channel {
+            Channel::Room(room) if room != DEFAULT_ROOM => {
+                wss.send(&ClientFrame::Join { room: room.clone() });
+            }
+            _ => {}
+        }
+        conversations.entry(channel).or_default();
+        for channel in conversations.keys() {
+            hydrate(ctx.link(), username.clone(), channel.clone());
         }
 
         Self {
-            users: vec![],
-            messages: vec![],
+            directory: Directory::default(),
+            conversations,
+            available_rooms: vec![],
             chat_input: NodeRef::default(),
+            messages_ref: NodeRef::default(),
+            scroll_anchor: None,
+            typing_sent: None,
+            typing_idle: None,
+            thread: None,
+            uploads: vec![],
+            next_upload: 0,
+            outbox: vec![],
+            next_client_id: 0,
+            files_base: endpoint::files_base(),
+            dragging: false,
+            mention: None,
+            mention_selected: 0,
+            commands: Registry::default(),
+            notice: None,
+            renaming: None,
             wss,
+            connection: ConnectionState::Connecting,
+            diagnostics: vec![],
+            now: Date::now(),
+            _clock: {
+                let link = ctx.link().clone();
+                Interval::new(CLOCK_INTERVAL_MS, move || link.send_message(Msg::Tick))
+            },
+            _typing_sweep: {
+                let link = ctx.link().clone();
+                Interval::new(TYPING_SWEEP_MS, move || link.send_message(Msg::ExpireTyping))
+            },
             _producer: EventBus::bridge(ctx.link().callback(Msg::HandleMsg)),
+            _connection: ConnectionBus::bridge(ctx.link().callback(Msg::ConnectionChanged)),
         }
     }
 
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        self.stop_typing();
+        self.thread = None;
+        self.mention = None;
+        let channel = ctx.props().channel.clone();
+        match self.conversations.get_mut(&channel) {
+            Some(conversation) => {
+                conversation.unread = 0;
+                conversation.mentions = 0;
+            }
+            None => {
+                if let Channel::Room(room) = &channel {
+                    self.wss.send(&ClientFrame::Join { room: room.clone() });
+                }
+                hydrate(ctx.link(), self.username(ctx), channel.clone());
+                if self.connection.is_open() {
+                    self.request_history(&channel, None);
+                }
+                self.conversations.insert(channel, Conversation::default());
+            }
+        }
+        true
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
             Msg::HandleMsg(s) => {
-                let msg: WebSocketMessage = serde_json::from_str(&s).unwrap();
-                match msg.message_type {
-                    MsgTypes::Users => {
-                        let users_from_message = msg.data_array.unwrap_or_default();
-                        self.users = users_from_message
-                            .iter()
-                            .map(|u| UserProfile {
-                                name: u.into(),
-                                avatar: format!(
-                                    "https://api.dicebear.com/8.x/adventurer-neutral/svg?seed={}",
-                                    u
-                                )
-                                    .into(),
-                            })
-                            .collect();
+                let frame = match ServerFrame::decode(&s) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        let severity = if e.is_unknown_type() {
+                            log::warn!("Ignoring frame: {}", e);
+                            Severity::Notice
+                        } else {
+                            log::error!("Dropping frame: {}", e);
+                            Severity::Error
+                        };
+                        diagnostics::push(
+                            &mut self.diagnostics,
+                            Diagnostic {
+                                severity,
+                                detail: e.to_string(),
+                                raw: Some(s),
+                            },
+                        );
                         return true;
                     }
-                    MsgTypes::Message => {
-                        let message_data: MessageData = serde_json::from_str(&msg.data.unwrap()).unwrap();
-                        self.messages.push(message_data);
+                };
+                match frame {
+                    ServerFrame::RegisterResult {
+                        status,
+                        nick,
+                        author,
+                        message,
+                        suggestions,
+                    } => {
+                        let me = self.username(ctx);
+                        if author.is_some() {
+                            let (user, _) = ctx.link().context::<User>(Callback::noop()).expect("Context to be set");
+                            *user.author.borrow_mut() = author;
+                        }
+                        match status {
+                            RegisterStatus::Accepted if nick != me => {
+                                let (user, _) = ctx.link().context::<User>(Callback::noop()).expect("Context to be set");
+                                *user.username.borrow_mut() = nick.clone();
+                                session::save(&nick);
+                                self.wss.set_register(&ClientFrame::register(nick.clone(), &session::token()));
+                                self.renaming = None;
+                                self.notice = Some(Notice::Info(vec![format!("You are now {}", nick)]));
+                            }
+                            RegisterStatus::Accepted => {}
+                            _ if self.renaming.take().is_some() => {
+                                let mut error = message;
+                                if !suggestions.is_empty() {
+                                    error = format!("{}. Try {}", error, suggestions.join(", "));
+                                }
+                                self.notice = Some(Notice::Error(error));
+                            }
+                            _ => {
+                                // Our own nick, restored or re-sent after a reconnect, now
+                                // belongs to someone else; nothing we send would get through.
+                                session::clear();
+                                let (user, _) = ctx.link().context::<User>(Callback::noop()).expect("Context to be set");
+                                user.username.borrow_mut().clear();
+                                *user.signed_out.borrow_mut() = Some(message);
+                                if let Some(history) = ctx.link().history() {
+                                    history.push(Route::Login);
+                                }
+                            }
+                        }
+                        true
+                    }
+                    ServerFrame::Users { users } => {
+                        self.directory.set_online(&users, Date::now());
+                        true
+                    }
+                    ServerFrame::Members { room, users } => {
+                        let channel = Channel::Room(room);
+                        match self.conversations.get_mut(&channel) {
+                            Some(joined) => {
+                                let now = Date::now();
+                                for user in &users {
+                                    self.directory.saw(user, now);
+                                }
+                                joined.members = users;
+                                channel == ctx.props().channel
+                            }
+                            None => false,
+                        }
+                    }
+                    ServerFrame::Rooms { rooms } => {
+                        self.available_rooms = rooms;
+                        true
+                    }
+                    ServerFrame::Message(message) => {
+                        self.now = Date::now();
+                        self.directory.saw(&message.from, self.now);
+                        let me = self.username(ctx);
+                        let own = message.is_from(&me, self.author(ctx).as_ref());
+                        if let Some(client_id) = message.client_id.as_ref().filter(|_| own) {
+                            self.outbox.retain(|o| o.client_id != *client_id);
+                        }
+                        // Sent under an earlier nick of ours, it still belongs with the recipient.
+                        let channel = message.channel.conversation(if own { &me } else { &message.from }, &me);
+                        // Rooms we left may still have frames in flight; DMs open on first message.
+                        if !self.conversations.contains_key(&channel) {
+                            if matches!(channel, Channel::Room(_)) {
+                                return false;
+                            }
+                            hydrate(ctx.link(), me.clone(), channel.clone());
+                            self.request_history(&channel, None);
+                        }
+                        let active = channel == ctx.props().channel;
+                        let conversation = self.conversations.entry(channel.clone()).or_default();
+                        conversation.typing.remove(&message.from);
+                        let added = conversation.merge(vec![message]);
+                        if !active {
+                            conversation.unread += added.len();
+                            conversation.mentions += added.iter().filter(|m| !own && m.mentions.contains(&me)).count();
+                        }
+                        persist(me, channel, added);
+                        true
+                    }
+                    ServerFrame::Update(message) => {
+                        let me = self.username(ctx);
+                        let own = message.is_from(&me, self.author(ctx).as_ref());
+                        let channel = message.channel.conversation(if own { &me } else { &message.from }, &me);
+                        let replaced = self
+                            .conversations
+                            .get_mut(&channel)
+                            .is_some_and(|c| c.replace(message.clone()));
+                        if replaced {
+                            persist(me, channel.clone(), vec![message]);
+                        }
+                        replaced && channel == ctx.props().channel
+                    }
+                    ServerFrame::History { channel, messages, complete } => {
+                        let me = self.username(ctx);
+                        let active = channel == ctx.props().channel;
+                        let page_start = messages.first().map(|m| m.id);
+                        let anchor = self
+                            .messages_ref
+                            .cast::<Element>()
+                            .map(|el| (el.scroll_height(), el.scroll_top()));
+                        let conversation = match self.conversations.get_mut(&channel) {
+                            Some(conversation) => conversation,
+                            None => return false,
+                        };
+                        let before = conversation.oldest();
+                        let received = messages.len();
+                        let added = conversation.merge(messages);
+                        conversation.loading_older = false;
+                        // A page sharing nothing with what we hold, yet newer than some of it, leaves
+                        // a hole of messages missed while away; keep paging back until they meet.
+                        let gap = !complete
+                            && received > 0
+                            && added.len() == received
+                            && before.zip(page_start).is_some_and(|(held, start)| held < start);
+                        // Only a page that reaches back to our first message says anything about what's older.
+                        if page_start.is_none() || page_start == conversation.oldest() {
+                            conversation.complete = complete;
+                        }
+                        if active && conversation.oldest() < before {
+                            self.scroll_anchor = anchor;
+                        }
+                        if gap {
+                            self.request_history(&channel, page_start);
+                        }
+                        persist(me, channel, added);
+                        active
+                    }
+                    ServerFrame::Typing { channel, from, typing } => {
+                        let me = self.username(ctx);
+                        let channel = channel.conversation(&from, &me);
+                        match self.conversations.get_mut(&channel) {
+                            Some(conversation) if from != me => {
+                                if typing {
+                                    conversation.typing.insert(from, Date::now() + TYPING_TIMEOUT_MS as f64);
+                                } else {
+                                    conversation.typing.remove(&from);
+                                }
+                                channel == ctx.props().channel
+                            }
+                            _ => false,
+                        }
+                    }
+                    ServerFrame::UploadProgress { upload, received } => {
+                        let Some(i) = self.uploads.iter().position(|u| u.id == upload && u.failed.is_none()) else {
+                            return false;
+                        };
+                        if received >= self.uploads[i].file.size() {
+                            // The message carrying the file is on its way.
+                            self.uploads.remove(i);
+                        } else {
+                            let pending = &mut self.uploads[i];
+                            pending.received = received;
+                            read_chunk(ctx.link(), upload, &pending.file, received);
+                        }
+                        true
+                    }
+                    ServerFrame::UploadFailed { upload, code, message } => {
+                        log::error!("Upload failed {:?}: {}", code, message);
+                        match self.uploads.iter_mut().find(|u| u.id == upload) {
+                            Some(failed) => {
+                                failed.failed = Some(message);
+                                true
+                            }
+                            None => false,
+                        }
+                    }
+                    ServerFrame::Error { code, message, client_id } => {
+                        log::error!("Server error {:?}: {}", code, message);
+                        if let Some(out) = self.outbox.iter_mut().find(|o| Some(&o.client_id) == client_id.as_ref()) {
+                            out.failed = Some(message.clone());
+                            out.ack_timeout = None;
+                        }
+                        diagnostics::push(
+                            &mut self.diagnostics,
+                            Diagnostic {
+                                severity: Severity::Error,
+                                detail: format!("Server error ({:?}): {}", code, message),
+                                raw: None,
+                            },
+                        );
+                        true
+                    }
+                }
+            }
+            Msg::ConnectionChanged(state) => {
+                let changed = self.connection != state;
+                if changed && !state.is_open() {
+                    // The server drops half-sent files with the connection.
+                    for upload in self.uploads.iter_mut().filter(|u| u.failed.is_none()) {
+                        upload.failed = Some("Connection lost".into());
+                    }
+                    for out in self.outbox.iter_mut() {
+                        out.ack_timeout = None;
+                    }
+                }
+                self.connection = state;
+                if changed && self.connection.is_open() {
+                    self.resync(ctx);
+                }
+                changed
+            }
+            Msg::SubmitMessage(text) => {
+                self.stop_typing();
+                self.mention = None;
+                self.notice = None;
+                match self.commands.parse(&text) {
+                    None => self.post(ctx, ctx.props().channel.clone(), text, None, false),
+                    // Left in the input to be fixed.
+                    Some(Err(usage)) => {
+                        self.notice = Some(Notice::Error(usage));
                         return true;
                     }
-                    _ => {
-                        return false;
+                    Some(Ok(action)) => self.run(ctx, action),
+                }
+                if let Some(input) = self.chat_input.cast::<HtmlTextAreaElement>() {
+                    input.set_value("");
+                }
+                true
+            }
+            Msg::EditMessage(id, text) => {
+                self.wss.send(&ClientFrame::Edit {
+                    channel: ctx.props().channel.clone(),
+                    id,
+                    mentions: self.mentions_in(&text),
+                    text,
+                });
+                false
+            }
+            Msg::DeleteMessage(id) => {
+                self.wss.send(&ClientFrame::Delete {
+                    channel: ctx.props().channel.clone(),
+                    id,
+                });
+                false
+            }
+            Msg::React(id, emoji, add) => {
+                let channel = ctx.props().channel.clone();
+                self.wss.send(&if add {
+                    ClientFrame::React { channel, id, emoji }
+                } else {
+                    ClientFrame::Unreact { channel, id, emoji }
+                });
+                false
+            }
+            Msg::OpenThread(root) => {
+                self.thread = Some(root);
+                true
+            }
+            Msg::CloseThread => {
+                self.thread = None;
+                true
+            }
+            Msg::SubmitReply(text) => {
+                if let Some(root) = self.thread {
+                    self.post(ctx, ctx.props().channel.clone(), text, Some(root), false);
+                }
+                false
+            }
+            Msg::Typed => {
+                let input = self.chat_input.cast::<HtmlTextAreaElement>();
+                let query = input.as_ref().and_then(|input| {
+                    let caret = input.selection_start().ok().flatten()?;
+                    mention::query(&input.value(), caret).map(|(at, prefix)| (at, prefix.to_string()))
+                });
+                let mention_changed = query != self.mention || matches!(self.notice, Some(Notice::Error(_)));
+                if matches!(self.notice, Some(Notice::Error(_))) {
+                    self.notice = None;
+                }
+                if mention_changed {
+                    self.mention = query;
+                    self.mention_selected = 0;
+                }
+                if input.is_none_or(|input| input.value().is_empty()) {
+                    self.stop_typing();
+                    return mention_changed;
+                }
+                let channel = ctx.props().channel.clone();
+                let now = Date::now();
+                let due = match &self.typing_sent {
+                    Some((sent, at)) => *sent != channel || now - at >= TYPING_REFRESH_MS as f64,
+                    None => true,
+                };
+                if due {
+                    self.wss.send(&ClientFrame::Typing {
+                        channel: channel.clone(),
+                        typing: true,
+                    });
+                    self.typing_sent = Some((channel, now));
+                }
+                let link = ctx.link().clone();
+                self.typing_idle = Some(Timeout::new(TYPING_IDLE_MS, move || link.send_message(Msg::StopTyping)));
+                mention_changed
+            }
+            Msg::StopTyping => {
+                self.stop_typing();
+                false
+            }
+            Msg::ExpireTyping => {
+                let now = Date::now();
+                let mut expired = false;
+                for (channel, conversation) in self.conversations.iter_mut() {
+                    let before = conversation.typing.len();
+                    conversation.typing.retain(|_, expires| *expires > now);
+                    expired |= *channel == ctx.props().channel && conversation.typing.len() != before;
+                }
+                expired
+            }
+            Msg::LeaveRoom(room) => {
+                let channel = Channel::Room(room.clone());
+                if room == DEFAULT_ROOM || self.conversations.remove(&channel).is_none() {
+                    return false;
+                }
+                self.wss.send(&ClientFrame::Leave { room });
+                if channel == ctx.props().channel {
+                    if let Some(history) = ctx.link().history() {
+                        history.push(channel_route(&Channel::Room(DEFAULT_ROOM.into())));
+                    }
+                }
+                true
+            }
+            Msg::OpenDirect(user) => {
+                if let Some(history) = ctx.link().history() {
+                    history.push(channel_route(&Channel::Direct(user)));
+                }
+                false
+            }
+            Msg::Hydrated(channel, stored) => match self.conversations.get_mut(&channel) {
+                Some(conversation) => {
+                    conversation.merge(stored);
+                    channel == ctx.props().channel
+                }
+                None => false,
+            },
+            Msg::LoadOlder => {
+                let channel = ctx.props().channel.clone();
+                let online = self.connection.is_open();
+                match self.conversations.get_mut(&channel) {
+                    Some(conversation) if online && !conversation.loading_older && !conversation.complete => {
+                        conversation.loading_older = true;
+                        let before = conversation.oldest();
+                        self.request_history(&channel, before);
+                        true
                     }
+                    _ => false,
                 }
             }
-            Msg::SubmitMessage => {
-                let input = self.chat_input.cast::<HtmlInputElement>();
-                if let Some(input) = input {
-                    let message = WebSocketMessage {
-                        message_type: MsgTypes::Message,
-                        data: Some(input.value()),
-                        data_array: None,
+            Msg::ClearHistory => {
+                let confirmed = gloo_utils::window()
+                    .confirm_with_message("Delete all chat history stored in this browser?")
+                    .unwrap_or(false);
+                if !confirmed {
+                    return false;
+                }
+                spawn_local(async {
+                    if let Err(e) = history::clear().await {
+                        log::warn!("Could not clear local history: {}", e);
+                    }
+                });
+                for conversation in self.conversations.values_mut() {
+                    conversation.messages.clear();
+                    conversation.unread = 0;
+                    conversation.mentions = 0;
+                    conversation.complete = false;
+                }
+                true
+            }
+            Msg::ClearDiagnostics => {
+                self.diagnostics.clear();
+                true
+            }
+            Msg::Tick => {
+                self.now = Date::now();
+                self.conversations.values().any(|c| !c.messages.is_empty())
+            }
+            Msg::Attach(picked) => {
+                let channel = ctx.props().channel.clone();
+                for file in picked {
+                    let file = File::from(file);
+                    let id = UploadId(self.next_upload);
+                    self.next_upload = self.next_upload.wrapping_add(1);
+                    let failed = if file.size() == 0 {
+                        Some("The file is empty".to_string())
+                    } else if file.size() > MAX_UPLOAD_BYTES {
+                        Some(format!("Files are limited to {}", format_size(MAX_UPLOAD_BYTES)))
+                    } else if !self.connection.is_open() {
+                        Some("Offline".to_string())
+                    } else {
+                        None
                     };
-                    if let Err(e) = self.wss.tx.clone().try_send(serde_json::to_string(&message).unwrap()) {
-                        log::debug!("Error sending to channel: {:?}", e);
+                    if failed.is_none() {
+                        self.wss.send(&ClientFrame::Upload {
+                            channel: channel.clone(),
+                            upload: id,
+                            name: file.name(),
+                            mime: file.raw_mime_type(),
+                            size: file.size(),
+                        });
+                        read_chunk(ctx.link(), id, &file, 0);
                     }
-                    input.set_value("");
+                    self.uploads.push(Upload {
+                        id,
+                        channel: channel.clone(),
+                        file,
+                        received: 0,
+                        failed,
+                    });
+                }
+                self.dragging = false;
+                true
+            }
+            Msg::ChunkRead(id, read) => {
+                let Some(upload) = self.uploads.iter_mut().find(|u| u.id == id && u.failed.is_none()) else {
+                    return false;
+                };
+                match read {
+                    Ok(data) => {
+                        self.wss.send_chunk(&Chunk {
+                            upload: id,
+                            index: (upload.received / UPLOAD_CHUNK_BYTES) as u32,
+                            data,
+                        });
+                        false
+                    }
+                    Err(e) => {
+                        upload.failed = Some(format!("Could not read the file: {}", e));
+                        true
+                    }
+                }
+            }
+            Msg::DismissUpload(id) => {
+                self.uploads.retain(|u| u.id != id);
+                true
+            }
+            Msg::Dragging(dragging) => {
+                let changed = self.dragging != dragging;
+                self.dragging = dragging;
+                changed
+            }
+            Msg::MentionMove(delta) => {
+                let count = self.mention_suggestions(ctx).len() as i32;
+                if count == 0 {
+                    return false;
+                }
+                self.mention_selected = (self.mention_selected as i32 + delta).rem_euclid(count) as usize;
+                true
+            }
+            Msg::MentionPick(name) => {
+                let name = name.or_else(|| self.mention_suggestions(ctx).get(self.mention_selected).cloned());
+                let (Some((at, _)), Some(name), Some(input)) =
+                    (self.mention.take(), name, self.chat_input.cast::<HtmlTextAreaElement>())
+                else {
+                    return true;
+                };
+                let (text, caret) = mention::complete(&input.value(), at, &name);
+                input.set_value(&text);
+                let _ = input.set_selection_range(caret, caret);
+                let _ = input.focus();
+                true
+            }
+            Msg::MentionClose => self.mention.take().is_some(),
+            Msg::DismissNotice => self.notice.take().is_some(),
+            Msg::AckTimeout(client_id) => match self.outbox.iter_mut().find(|o| o.client_id == client_id) {
+                Some(out) if out.failed.is_none() => {
+                    out.failed = Some("No reply from the server".into());
+                    out.ack_timeout = None;
+                    true
+                }
+                _ => false,
+            },
+            Msg::Resend(client_id) => {
+                self.transmit(ctx, &client_id);
+                true
+            }
+            Msg::Logout => {
+                let unsent = self.outbox.len();
+                if unsent > 0 {
+                    let confirmed = gloo_utils::window()
+                        .confirm_with_message(&format!("{} message(s) haven't been sent yet. Log out anyway?", unsent))
+                        .unwrap_or(false);
+                    if !confirmed {
+                        return false;
+                    }
+                }
+                self.stop_typing();
+                session::clear();
+                let (user, _) = ctx.link().context::<User>(Callback::noop()).expect("Context to be set");
+                user.username.borrow_mut().clear();
+                // Unmounting drops the connection, which the server takes as leaving.
+                if let Some(history) = ctx.link().history() {
+                    history.push(Route::Login);
+                }
+                false
+            }
+            Msg::Discard(client_id) => {
+                self.outbox.retain(|o| o.client_id != client_id);
+                true
+            }
+            Msg::JumpToMention => {
+                let first = self.conversations.iter().find(|(_, c)| c.mentions > 0).map(|(channel, _)| channel);
+                if let (Some(channel), Some(history)) = (first, ctx.link().history()) {
+                    history.push(channel_route(channel));
                 }
                 false
             }
         }
     }
 
+    fn rendered(&mut self, _ctx: &Context<Self>, _first_render: bool) {
+        if let Some((height, top)) = self.scroll_anchor.take() {
+            if let Some(list) = self.messages_ref.cast::<Element>() {
+                list.set_scroll_top(list.scroll_height() - height + top);
+            }
+        }
+    }
+
     fn view(&self, ctx: &Context<Self>) -> Html {
-        let submit = ctx.link().callback(|_| Msg::SubmitMessage);
-        let (cur_user, _) = ctx.link().context::<User>(Callback::noop()).expect("Context to be set");
-        let cur_username = cur_user.username.borrow().clone();
+        let clear_diagnostics = ctx.link().callback(|_| Msg::ClearDiagnostics);
+        let clear_history = ctx.link().callback(|_| Msg::ClearHistory);
+        let logout = ctx.link().callback(|_| Msg::Logout);
+        let cur_username = self.username(ctx);
+        let online = self.connection.is_open();
+        let channel = ctx.props().channel.clone();
+        let title = channel_label(&channel);
+        let (messages, room_members, loading_older, complete) = match self.conversations.get(&channel) {
+            Some(c) => (c.messages.as_slice(), c.members.clone(), c.loading_older, c.complete),
+            None => (&[][..], vec![], false, false),
+        };
+        let timeline = Timeline::new(messages, &cur_username, self.author(ctx).as_ref());
+        let onscroll = ctx.link().batch_callback(|e: Event| {
+            let list: Element = e.target_unchecked_into();
+            (list.scroll_top() <= LOAD_OLDER_THRESHOLD_PX).then_some(Msg::LoadOlder)
+        });
+        let load_older = ctx.link().callback(|_| Msg::LoadOlder);
+        let typing = self
+            .conversations
+            .get(&channel)
+            .and_then(|c| typing_label(&c.typing.keys().collect::<Vec<_>>()));
+        // A DM has no member list from the server: it's us and whichever of the peer is online.
+        let members = match &channel {
+            Channel::Room(_) => room_members,
+            Channel::Direct(peer) => {
+                let peer_online = self.directory.get(peer).is_some_and(|p| p.online);
+                let mut members = vec![cur_username.clone()];
+                if peer_online && *peer != cur_username {
+                    members.push(peer.clone());
+                }
+                members
+            }
+        };
+        let members = members.as_slice();
+        let peer = match &channel {
+            Channel::Direct(peer) => Some(peer.as_str()),
+            Channel::Room(_) => None,
+        };
+        let channels: Vec<ChannelEntry> = self
+            .conversations
+            .iter()
+            .map(|(channel, conversation)| ChannelEntry {
+                channel: channel.clone(),
+                unread: conversation.unread,
+                mentions: conversation.mentions,
+            })
+            .collect();
+        let leave_room = ctx.link().callback(Msg::LeaveRoom);
+        let dragging = self.dragging;
+        let ondragover = ctx.link().batch_callback(move |e: DragEvent| {
+            // Browsers only allow dropping where `dragover` is cancelled.
+            e.prevent_default();
+            (!dragging).then_some(Msg::Dragging(true))
+        });
+        let ondragleave = ctx.link().callback(|_: DragEvent| Msg::Dragging(false));
+        let ondrop = ctx.link().callback(|e: DragEvent| {
+            e.prevent_default();
+            Msg::Attach(files(e.data_transfer().and_then(|d| d.files())))
+        });
+        let onpaste = ctx.link().batch_callback(|e: Event| {
+            // `ClipboardEvent` is still behind web-sys' unstable APIs.
+            let pasted = files(
+                js_sys::Reflect::get(&e, &"clipboardData".into())
+                    .ok()
+                    .and_then(|d| d.dyn_into::<DataTransfer>().ok())
+                    .and_then(|d| d.files()),
+            );
+            // Pasting text goes into the input as usual.
+            if pasted.is_empty() {
+                return None;
+            }
+            e.prevent_default();
+            Some(Msg::Attach(pasted))
+        });
+        let suggestions = self.mention_suggestions(ctx);
+        let suggesting = !suggestions.is_empty();
+        let onkeydown = ctx.link().batch_callback(move |e: KeyboardEvent| {
+            if !suggesting {
+                return None;
+            }
+            let msg = match e.key().as_str() {
+                "ArrowDown" => Msg::MentionMove(1),
+                "ArrowUp" => Msg::MentionMove(-1),
+                "Enter" | "Tab" => Msg::MentionPick(None),
+                "Escape" => Msg::MentionClose,
+                _ => return None,
+            };
+            e.prevent_default();
+            Some(msg)
+        });
+        let mentions: usize = self.conversations.values().map(|c| c.mentions).sum();
+        let jump_to_mention = ctx.link().callback(|_| Msg::JumpToMention);
+        let pick_files = ctx.link().callback(|e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let picked = files(input.files());
+            // Lets the same file be picked again.
+            input.set_value("");
+            Msg::Attach(picked)
+        });
+        let (status_dot, status_text) = match &self.connection {
+            ConnectionState::Connecting => ("bg-yellow-400 animate-pulse", "Connecting...".to_string()),
+            ConnectionState::Open => ("bg-green-500 animate-pulse", "Connected".to_string()),
+            ConnectionState::Reconnecting(attempt) => {
+                ("bg-orange-400 animate-pulse", format!("Reconnecting (attempt {})...", attempt))
+            }
+            ConnectionState::Closed(reason) => ("bg-red-500", format!("Disconnected: {}", reason)),
+        };
 
         html! {
             <div class="fixed inset-0 w-full h-full bg-gradient-to-br from-purple-900 via-blue-900 to-indigo-900 flex relative overflow-hidden">
@@ -142,7 +1315,7 @@ impl Component for Chat {
                 <div class="absolute -bottom-8 left-40 w-96 h-96 bg-indigo-500 rounded-full mix-blend-multiply filter blur-xl opacity-10 animate-pulse animation-delay-4000"></div>
 
                 // Users Sidebar
-                <div class="relative z-10 flex-none w-80 h-full bg-white/5 backdrop-blur-lg border-r border-white/10">
+                <div class="relative z-10 flex-none w-80 h-full flex flex-col bg-white/5 backdrop-blur-lg border-r border-white/10">
                     <div class="p-6 border-b border-white/10">
                         <div class="flex items-center space-x-3">
                             <div class="w-12 h-12 bg-gradient-to-r from-purple-500 to-blue-500 rounded-full flex items-center justify-center shadow-lg">
@@ -152,23 +1325,39 @@ impl Component for Chat {
                             </div>
                             <div>
                                 <h2 class="text-xl font-bold text-white">{"Online Users"}</h2>
-                                <p class="text-sm text-gray-300">{format!("{} users online", self.users.len())}</p>
+                                <p class="text-sm text-gray-300">{format!("{} in {}", members.len(), title)}</p>
                             </div>
                         </div>
                     </div>
 
-                    <div class="p-4 space-y-3 overflow-y-auto h-full">
+                    <ChannelList {channels} available={self.available_rooms.clone()} active={channel.clone()} on_leave={leave_room} />
+
+                    <div class="flex-grow p-4 space-y-3 overflow-y-auto">
                         {
-                            self.users.clone().iter().map(|u| {
+                            self.directory.roster(members, messages.iter().map(|m| m.from.as_str()).chain(peer)).into_iter().map(|u| {
                                 let is_current_user = u.name == cur_username;
+                                let onclick = (!is_current_user).then(|| {
+                                    let name = u.name.clone();
+                                    ctx.link().callback(move |_| Msg::OpenDirect(name.clone()))
+                                });
+                                // Presence here is per room: online elsewhere still counts as gone.
+                                let in_room = members.contains(&u.name);
+                                let presence_dot = if in_room { "bg-green-500" } else { "bg-gray-500" };
+                                let presence = if in_room {
+                                    "Active now"
+                                } else if u.online {
+                                    "Left the room"
+                                } else {
+                                    "Offline"
+                                };
                                 let bg_color = if is_current_user { "bg-green-500/20 border-green-400/50" } else { "bg-white/5 hover:bg-white/10 border-white/5 hover:border-white/20" };
                                 let text_color = if is_current_user { "text-green-300" } else { "text-white group-hover:text-purple-300" };
 
                                 html!{
-                                    <div class={format!("group flex items-center space-x-3 {} rounded-xl p-3 transition-all duration-300 cursor-pointer border", bg_color)}>
+                                    <div {onclick} title={if is_current_user { None } else { Some(format!("Message {}", u.name)) }} class={format!("group flex items-center space-x-3 {} {} rounded-xl p-3 transition-all duration-300 cursor-pointer border", bg_color, if in_room { "" } else { "opacity-50" })}>
                                         <div class="relative">
-                                            <img class="w-12 h-12 rounded-full ring-2 ring-purple-500/50 shadow-lg" src={u.avatar.clone()} alt="avatar"/>
-                                            <div class="absolute bottom-0 right-0 w-4 h-4 bg-green-500 rounded-full border-2 border-white"></div>
+                                            <img class={format!("w-12 h-12 rounded-full ring-2 ring-purple-500/50 shadow-lg {}", if in_room { "" } else { "grayscale" })} src={u.avatar.clone()} alt="avatar"/>
+                                            <div class={format!("absolute bottom-0 right-0 w-4 h-4 {} rounded-full border-2 border-white", presence_dot)}></div>
                                         </div>
                                         <div class="flex-grow">
                                             <div class={format!("font-medium transition-colors {}", text_color)}>
@@ -178,7 +1367,7 @@ impl Component for Chat {
                                                 }
                                             </div>
                                             <div class="text-xs text-gray-400">
-                                                {"Active now"}
+                                                {presence}
                                             </div>
                                         </div>
                                         <div class="w-2 h-2 bg-purple-500 rounded-full opacity-0 group-hover:opacity-100 transition-opacity"></div>
@@ -190,7 +1379,12 @@ impl Component for Chat {
                 </div>
 
                 // Chat Area
-                <div class="relative z-10 flex-grow h-full flex flex-col bg-white/5 backdrop-blur-lg">
+                <div {ondragover} {ondrop} class="relative z-10 flex-grow h-full flex flex-col bg-white/5 backdrop-blur-lg">
+                    if self.dragging {
+                        <div {ondragleave} class="absolute inset-4 z-30 flex items-center justify-center rounded-2xl border-2 border-dashed border-purple-400 bg-purple-900/60">
+                            <span class="pointer-events-none text-xl font-semibold text-white">{format!("Drop files to send them to {}", title)}</span>
+                        </div>
+                    }
                     // Chat Header
                     <div class="flex items-center justify-between p-6 border-b border-white/10 bg-white/5">
                         <div class="flex items-center space-x-4">
@@ -200,121 +1394,187 @@ impl Component for Chat {
                                 </svg>
                             </div>
                             <div>
-                                <h1 class="text-2xl font-bold text-white">{"Global Chat"}</h1>
-                                <p class="text-sm text-gray-300">{"Connect with everyone"}</p>
+                                <h1 class="text-2xl font-bold text-white">{title}</h1>
+                                <p class="text-sm text-gray-300">
+                                    {match &channel {
+                                        Channel::Room(room) if room == DEFAULT_ROOM => "Connect with everyone".to_string(),
+                                        Channel::Room(_) => format!("{} members", members.len()),
+                                        Channel::Direct(_) => "Direct message".to_string(),
+                                    }}
+                                </p>
                             </div>
                         </div>
-                        <div class="flex items-center space-x-2">
-                            <div class="w-3 h-3 bg-green-500 rounded-full animate-pulse"></div>
-                            <span class="text-sm text-gray-300">{"Connected"}</span>
+                        <div class="flex items-center space-x-4">
+                            if mentions > 0 {
+                                <button
+                                    onclick={jump_to_mention}
+                                    title={if mentions == 1 { "1 unread mention".to_string() } else { format!("{} unread mentions", mentions) }}
+                                    class="px-3 py-1 rounded-full bg-amber-500 text-gray-900 text-sm font-semibold shadow-lg hover:bg-amber-400"
+                                >
+                                    {format!("@ {}", mentions)}
+                                </button>
+                            }
+                            <button
+                                onclick={clear_history}
+                                title="Clear local history"
+                                class="p-2 rounded-lg text-gray-400 hover:text-red-300 hover:bg-white/10 transition-colors"
+                            >
+                                <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M19 7l-.867 12.142A2 2 0 0116.138 21H7.862a2 2 0 01-1.995-1.858L5 7m5 4v6m4-6v6m1-10V4a1 1 0 00-1-1h-4a1 1 0 00-1 1v3M4 7h16"></path>
+                                </svg>
+                            </button>
+                            <Diagnostics entries={self.diagnostics.clone()} on_clear={clear_diagnostics} />
+                            <div class="flex items-center space-x-2">
+                                <div class={format!("w-3 h-3 rounded-full {}", status_dot)}></div>
+                                <span class="text-sm text-gray-300">{status_text}</span>
+                            </div>
+                            <button
+                                onclick={logout}
+                                title={format!("Log out {}", cur_username)}
+                                class="p-2 rounded-lg text-gray-400 hover:text-white hover:bg-white/10 transition-colors"
+                            >
+                                <svg class="w-5 h-5" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M17 16l4-4m0 0l-4-4m4 4H7m6 4v1a3 3 0 01-3 3H6a3 3 0 01-3-3V7a3 3 0 013-3h4a3 3 0 013 3v1"></path>
+                                </svg>
+                            </button>
                         </div>
                     </div>
 
                     // Messages Area
-                    <div class="flex-grow overflow-y-auto p-6 space-y-4">
+                    <div ref={self.messages_ref.clone()} {onscroll} class="flex-grow overflow-y-auto p-6 space-y-4">
+                        <div class="flex justify-center">
+                            if loading_older {
+                                <span class="text-xs text-gray-400 animate-pulse">{"Loading older messages..."}</span>
+                            } else if complete {
+                                <span class="text-xs text-gray-500">{"Beginning of the conversation"}</span>
+                            } else if online {
+                                <button onclick={load_older} class="text-xs text-purple-300 hover:text-purple-200">{"Load older messages"}</button>
+                            }
+                        </div>
                         {
-                            self.messages.iter().map(|m| {
-                                let user = self.users.iter().find(|u| u.name == m.from).unwrap();
-                                let is_current_user = user.name == cur_username;
-                                let message_container_class = if is_current_user {
-                                    "flex justify-end" // Ini akan mendorong pesan ke kanan
-                                } else {
-                                    "flex justify-start" // Ini akan memposisikan pesan di kiri
-                                };
-                                let bubble_color = if is_current_user {
-                                    "bg-green-500/20 border-green-400/30"
-                                } else {
-                                    "bg-white/10 border-white/10"
-                                };
-                                let bubble_rounding = if is_current_user {
-                                    "rounded-2xl rounded-tr-none"
-                                } else {
-                                    "rounded-2xl rounded-tl-none"
-                                };
-                                let name_color = if is_current_user {
-                                    "text-green-300"
-                                } else {
-                                    "text-purple-300"
-                                };
-
+                            messages.iter().enumerate().map(|(i, m)| {
+                                let new_day = i == 0 || !same_day(millis(messages[i - 1].time), millis(m.time));
                                 html! {
-                                    <div class={message_container_class}>
-                                        <div class={format!("flex items-start space-x-3 max-w-4xl")}>
-                                            {if !is_current_user {
-                                                html! {
-                                                    <img class="w-10 h-10 rounded-full ring-2 ring-purple-500/30 shadow-lg flex-shrink-0"
-                                                        src={user.avatar.clone()} alt="avatar"/>
-                                                }
-                                            } else {
-                                                html! {}
-                                            }}
-                                            <div class={format!("flex flex-col {}", if is_current_user { "items-end" } else { "items-start" })}>
-                                                <div class={format!("backdrop-blur-sm p-4 border shadow-lg {} {}", bubble_color, bubble_rounding)}>
-                                                    <div class="flex items-center space-x-2 mb-2">
-                                                        <span class={format!("text-sm font-semibold {}", name_color)}>
-                                                            {m.from.clone()}
-                                                            if is_current_user {
-                                                                {" (You)"}
-                                                            }
-                                                        </span>
-                                                        <span class="text-xs text-gray-400">
-                                                            {"just now"}
-                                                        </span>
-                                                    </div>
-                                                    <div class="text-white">
-                                                        if m.message.ends_with(".gif") {
-                                                            <img class="mt-2 rounded-lg max-w-xs shadow-lg" src={m.message.clone()}/>
-                                                        } else {
-                                                            <p class="break-words">{m.message.clone()}</p>
-                                                        }
-                                                    </div>
-                                                </div>
-                                            </div>
-                                            {if is_current_user {
-                                                html! {
-                                                    <img class="w-10 h-10 rounded-full ring-2 ring-purple-500/30 shadow-lg flex-shrink-0"
-                                                        src={user.avatar.clone()} alt="avatar"/>
-                                                }
-                                            } else {
-                                                html! {}
-                                            }}
-                                        </div>
-                                    </div>
+                                    <>
+                                    if new_day {
+                                        <DaySeparator time={m.time} now={self.now} />
+                                    }
+                                    {self.bubble(ctx, m, &timeline, false)}
+                                    </>
                                 }
                             }).collect::<Html>()
                         }
+                        { for self.outbox.iter().filter(|o| o.channel == channel).map(|o| self.pending_bubble(ctx, o)) }
                     </div>
 
                     // Message Input
-                    <div class="p-6 border-t border-white/10 bg-white/5">
-                        <div class="flex items-center space-x-4">
-                            <div class="flex-grow relative">
-                                <input
-                                    ref={self.chat_input.clone()}
-                                    type="text"
-                                    placeholder="Type your message..."
-                                    class="w-full py-4 pl-6 pr-16 bg-white/10 border border-white/20 rounded-2xl text-white placeholder-gray-400 focus:outline-none focus:ring-2 focus:ring-purple-500 focus:border-transparent backdrop-blur-sm transition-all duration-300 hover:bg-white/20"
-                                    name="message"
-                                    required=true
-                                />
-                                <div class="absolute inset-y-0 right-0 flex items-center pr-4">
-                                    <svg class="w-5 h-5 text-gray-400" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                                        <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M7 4V2a1 1 0 011-1h8a1 1 0 011 1v2M7 4h10M7 4l-4 4v10a2 2 0 002 2h14a2 2 0 002-2V8l-4-4"></path>
-                                    </svg>
-                                </div>
-                            </div>
-                            <button
-                                onclick={submit}
-                                class="p-4 bg-gradient-to-r from-purple-600 to-blue-600 hover:from-purple-700 hover:to-blue-700 rounded-2xl shadow-lg hover:shadow-xl transform hover:scale-105 transition-all duration-300 flex items-center justify-center group"
-                            >
-                                <svg class="w-6 h-6 text-white group-hover:rotate-45 transition-transform duration-300" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 19l9 2-9-18-9 18 9-2zm0 0v-8"></path>
-                                </svg>
-                            </button>
+                    <div class="px-6 pb-6 pt-2 border-t border-white/10 bg-white/5">
+                        <div class="h-5 mb-2 text-xs text-gray-400 italic">
+                            {typing.unwrap_or_default()}
                         </div>
+                        if let Some(notice) = &self.notice {
+                            <div class={classes!("relative", "mb-2", "px-4", "py-2", "pr-8", "rounded-lg", "border", "text-sm", match notice {
+                                Notice::Error(_) => "bg-red-500/10 border-red-400/30 text-red-200",
+                                Notice::Info(_) => "bg-white/5 border-white/10 text-gray-200",
+                            })}>
+                                <button onclick={ctx.link().callback(|_| Msg::DismissNotice)} title="Dismiss" class="absolute top-1 right-2 text-gray-400 hover:text-white">{"×"}</button>
+                                {
+                                    match notice {
+                                        Notice::Error(error) => html! { <p>{error.clone()}</p> },
+                                        Notice::Info(lines) => lines.iter().map(|line| html! { <p class="font-mono">{line.clone()}</p> }).collect::<Html>(),
+                                    }
+                                }
+                            </div>
+                        }
+                        {
+                            for self.uploads.iter().filter(|u| u.channel == channel).map(|u| {
+                                let size = u.file.size();
+                                let dismiss = {
+                                    let id = u.id;
+                                    ctx.link().callback(move |_| Msg::DismissUpload(id))
+                                };
+                                html! {
+                                    <div key={u.id.0.to_string()} class="flex items-center space-x-3 mb-2 px-3 py-2 rounded-lg bg-white/5 border border-white/10 text-sm">
+                                        <span class="text-white truncate max-w-xs">{u.file.name()}</span>
+                                        if let Some(reason) = &u.failed {
+                                            <span class="flex-grow text-xs text-red-300">{reason.clone()}</span>
+                                            <button onclick={dismiss} title="Dismiss" class="text-gray-400 hover:text-white">{"×"}</button>
+                                        } else {
+                                            <div class="flex-grow h-1.5 rounded-full bg-white/10 overflow-hidden">
+                                                <div class="h-full bg-purple-500 transition-all" style={format!("width: {}%", u.received * 100 / size.max(1))}></div>
+                                            </div>
+                                            <span class="text-xs text-gray-400">{format!("{} / {}", format_size(u.received), format_size(size))}</span>
+                                        }
+                                    </div>
+                                }
+                            })
+                        }
+                        <Composer
+                            input_ref={self.chat_input.clone()}
+                            {online}
+                            on_submit={ctx.link().callback(Msg::SubmitMessage)}
+                            on_input={ctx.link().callback(|_| Msg::Typed)}
+                            on_keydown={onkeydown}
+                            on_paste={onpaste}
+                        >
+                                if suggesting {
+                                    <ul class="absolute bottom-full left-0 z-20 mb-2 w-64 py-1 rounded-xl bg-gray-900/95 border border-white/10 shadow-xl">
+                                        {
+                                            for suggestions.into_iter().enumerate().map(|(i, name)| {
+                                                let selected = i == self.mention_selected;
+                                                let avatar = self.directory.get(&name).map(|p| p.avatar.clone()).unwrap_or_else(|| avatar_url(&name));
+                                                // `mousedown` fires before the input loses focus.
+                                                let onmousedown = {
+                                                    let name = name.clone();
+                                                    ctx.link().callback(move |e: MouseEvent| {
+                                                        e.prevent_default();
+                                                        Msg::MentionPick(Some(name.clone()))
+                                                    })
+                                                };
+                                                html! {
+                                                    <li {onmousedown} class={classes!("flex", "items-center", "space-x-2", "px-3", "py-1.5", "cursor-pointer", "text-sm", "text-white", if selected { "bg-purple-500/40" } else { "hover:bg-white/10" })}>
+                                                        <img class="w-6 h-6 rounded-full" src={avatar} alt="avatar"/>
+                                                        <span>{name}</span>
+                                                    </li>
+                                                }
+                                            })
+                                        }
+                                    </ul>
+                                }
+                                <label title="Attach files" class="absolute inset-y-0 right-0 flex items-center pr-4 cursor-pointer">
+                                    <input onchange={pick_files} type="file" multiple=true class="hidden" />
+                                    <svg class="w-5 h-5 text-gray-400 hover:text-white" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                        <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M15.172 7l-6.586 6.586a2 2 0 102.828 2.828l6.414-6.586a4 4 0 00-5.656-5.656l-6.415 6.585a6 6 0 108.486 8.486L20.5 13"></path>
+                                    </svg>
+                                </label>
+                        </Composer>
                     </div>
                 </div>
 
+                if let Some(root) = self.thread {
+                    <ThreadPanel
+                        replies={timeline.replies.get(&root).copied().unwrap_or_default()}
+                        {online}
+                        on_reply={ctx.link().callback(Msg::SubmitReply)}
+                        on_close={ctx.link().callback(|_| Msg::CloseThread)}
+                    >
+                        {
+                            messages
+                                .iter()
+                                .filter(|m| m.id == root || m.reply_to == Some(root))
+                                .map(|m| self.bubble(ctx, m, &timeline, true))
+                                .collect::<Html>()
+                        }
+                        {
+                            for self
+                                .outbox
+                                .iter()
+                                .filter(|o| o.channel == channel && o.reply_to == Some(root))
+                                .map(|o| self.pending_bubble(ctx, o))
+                        }
+                    </ThreadPanel>
+                }
+
                 // Floating decorative elements
                 <div class="absolute top-1/4 right-8 w-4 h-4 bg-purple-500 rounded-full opacity-60 animate-ping"></div>
                 <div class="absolute top-3/4 left-8 w-3 h-3 bg-blue-500 rounded-full opacity-60 animate-ping animation-delay-1000"></div>
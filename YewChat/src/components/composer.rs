@@ -0,0 +1,151 @@
+use web_sys::HtmlTextAreaElement;
+use yew::functional::*;
+use yew::prelude::*;
+use yewchat_protocol::MAX_MESSAGE_LEN;
+
+/// Tallest the message box grows before it scrolls instead.
+const MAX_HEIGHT_PX: i32 = 200;
+
+#[derive(Properties, PartialEq)]
+pub struct ComposerProps {
+    /// The message box, for the parent to read, clear or complete into.
+    pub input_ref: NodeRef,
+    /// Messages can only be sent while connected.
+    pub online: bool,
+    /// The trimmed text, never empty. The parent clears the box once it is handled.
+    pub on_submit: Callback<String>,
+    pub on_input: Callback<()>,
+    /// Sees every key first; keys it cancels with `prevent_default` are left alone.
+    pub on_keydown: Callback<KeyboardEvent>,
+    pub on_paste: Callback<Event>,
+    /// Drawn over the message box, like the attach button and suggestion popup.
+    #[prop_or_default]
+    pub children: Children,
+}
+
+/// Sizes the message box to its text, up to [`MAX_HEIGHT_PX`].
+pub fn fit(input: &HtmlTextAreaElement) {
+    let style = input.style();
+    let _ = style.set_property("height", "auto");
+    let height = input.scroll_height().min(MAX_HEIGHT_PX);
+    let _ = style.set_property("height", &format!("{}px", height));
+}
+
+/// The message form: Enter sends, Shift+Enter starts a new line, and Up in an empty
+/// box brings back the last message sent.
+#[function_component(Composer)]
+pub fn composer(props: &ComposerProps) -> Html {
+    let last_sent = use_mut_ref(|| None::<String>);
+
+    {
+        // The parent may have cleared or completed the text since the last render.
+        let input_ref = props.input_ref.clone();
+        use_effect(move || {
+            if let Some(input) = input_ref.cast::<HtmlTextAreaElement>() {
+                fit(&input);
+            }
+            || ()
+        });
+    }
+
+    let submit = {
+        let input_ref = props.input_ref.clone();
+        let on_submit = props.on_submit.clone();
+        let last_sent = last_sent.clone();
+        let online = props.online;
+        move || {
+            let Some(input) = input_ref.cast::<HtmlTextAreaElement>() else {
+                return;
+            };
+            let text = input.value().trim().to_string();
+            // `maxlength` stops typing and pasting past the limit, but not every way in.
+            if text.encode_utf16().count() > MAX_MESSAGE_LEN {
+                return;
+            }
+            if text.is_empty() {
+                input.set_value("");
+                fit(&input);
+            } else if online {
+                *last_sent.borrow_mut() = Some(text.clone());
+                on_submit.emit(text);
+            }
+        }
+    };
+    let onsubmit = {
+        let submit = submit.clone();
+        Callback::from(move |e: FocusEvent| {
+            e.prevent_default();
+            submit();
+        })
+    };
+    let onkeydown = {
+        let on_keydown = props.on_keydown.clone();
+        let on_input = props.on_input.clone();
+        let input_ref = props.input_ref.clone();
+        Callback::from(move |e: KeyboardEvent| {
+            on_keydown.emit(e.clone());
+            // Enter also confirms an IME composition, which shouldn't send.
+            if e.default_prevented() || e.is_composing() {
+                return;
+            }
+            match e.key().as_str() {
+                "Enter" if !e.shift_key() => {
+                    e.prevent_default();
+                    submit();
+                }
+                "ArrowUp" => {
+                    let (Some(input), Some(last)) = (input_ref.cast::<HtmlTextAreaElement>(), last_sent.borrow().clone()) else {
+                        return;
+                    };
+                    if !input.value().is_empty() {
+                        return;
+                    }
+                    e.prevent_default();
+                    input.set_value(&last);
+                    let end = last.encode_utf16().count() as u32;
+                    let _ = input.set_selection_range(end, end);
+                    fit(&input);
+                    on_input.emit(());
+                }
+                _ => {}
+            }
+        })
+    };
+    let oninput = {
+        let on_input = props.on_input.clone();
+        Callback::from(move |e: InputEvent| {
+            fit(&e.target_unchecked_into());
+            on_input.emit(());
+        })
+    };
+    let onpaste = props.on_paste.reform(|e: Event| e);
+
+    html! {
+        <form {onsubmit} class="flex items-end space-x-4">
+            <div class="flex-grow relative">
+                <textarea
+                    ref={props.input_ref.clone()}
+                    {oninput}
+                    {onkeydown}
+                    {onpaste}
+                    rows="1"
+                    maxlength={MAX_MESSAGE_LEN.to_string()}
+                    placeholder="Type your message... (Shift+Enter for a new line)"
+                    class="block w-full py-4 pl-6 pr-16 resize-none overflow-y-auto bg-white/10 border border-white/20 rounded-2xl text-white placeholder-gray-400 focus:outline-none focus:ring-2 focus:ring-purple-500 focus:border-transparent backdrop-blur-sm transition-colors duration-300 hover:bg-white/20"
+                    name="message"
+                />
+                { for props.children.iter() }
+            </div>
+            <button
+                type="submit"
+                disabled={!props.online}
+                title={if props.online { "Send" } else { "Offline - waiting for the connection" }}
+                class="p-4 bg-gradient-to-r from-purple-600 to-blue-600 hover:from-purple-700 hover:to-blue-700 disabled:from-gray-600 disabled:to-gray-700 disabled:opacity-50 disabled:cursor-not-allowed rounded-2xl shadow-lg hover:shadow-xl transform hover:scale-105 disabled:hover:scale-100 transition-all duration-300 flex items-center justify-center group"
+            >
+                <svg class="w-6 h-6 text-white group-hover:rotate-45 transition-transform duration-300" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M12 19l9 2-9-18-9 18 9-2zm0 0v-8"></path>
+                </svg>
+            </button>
+        </form>
+    }
+}
@@ -0,0 +1,96 @@
+use yew::prelude::*;
+
+/// Keep only the most recent entries so a chatty broken server can't grow this forever.
+pub const MAX_DIAGNOSTICS: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    /// Something we chose to ignore, e.g. a frame type from a newer server.
+    Notice,
+    /// A frame we couldn't use or an error reported by the server.
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub detail: String,
+    /// The offending frame, if there was one.
+    pub raw: Option<String>,
+}
+
+/// Appends `entry`, dropping the oldest ones past [`MAX_DIAGNOSTICS`].
+pub fn push(log: &mut Vec<Diagnostic>, entry: Diagnostic) {
+    log.push(entry);
+    if log.len() > MAX_DIAGNOSTICS {
+        let excess = log.len() - MAX_DIAGNOSTICS;
+        log.drain(..excess);
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct DiagnosticsProps {
+    pub entries: Vec<Diagnostic>,
+    pub on_clear: Callback<()>,
+}
+
+/// Collapsible list of protocol problems, hidden entirely while there are none.
+#[function_component(Diagnostics)]
+pub fn diagnostics(props: &DiagnosticsProps) -> Html {
+    let open = use_state(|| false);
+
+    if props.entries.is_empty() {
+        return html! {};
+    }
+
+    let errors = props
+        .entries
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    let toggle = {
+        let open = open.clone();
+        Callback::from(move |_| open.set(!*open))
+    };
+    let clear = {
+        let on_clear = props.on_clear.clone();
+        Callback::from(move |_| on_clear.emit(()))
+    };
+    let badge = if errors > 0 { "bg-red-500/30 text-red-200" } else { "bg-yellow-500/20 text-yellow-200" };
+
+    html! {
+        <div class="relative">
+            <button
+                onclick={toggle}
+                title="Protocol diagnostics"
+                class={format!("px-3 py-1 rounded-full text-xs font-medium border border-white/10 {}", badge)}
+            >
+                {format!("{} issue{}", props.entries.len(), if props.entries.len() == 1 { "" } else { "s" })}
+            </button>
+            if *open {
+                <div class="absolute right-0 mt-2 w-96 max-h-96 overflow-y-auto z-20 bg-gray-900/95 backdrop-blur-lg border border-white/10 rounded-xl shadow-2xl p-4 space-y-3">
+                    <div class="flex items-center justify-between">
+                        <span class="text-sm font-semibold text-white">{"Protocol diagnostics"}</span>
+                        <button onclick={clear} class="text-xs text-purple-300 hover:text-purple-200">{"Clear"}</button>
+                    </div>
+                    {
+                        props.entries.iter().rev().map(|d| {
+                            let color = match d.severity {
+                                Severity::Notice => "text-yellow-200",
+                                Severity::Error => "text-red-300",
+                            };
+                            html! {
+                                <div class="border-t border-white/10 pt-2">
+                                    <p class={format!("text-xs {}", color)}>{d.detail.clone()}</p>
+                                    if let Some(raw) = &d.raw {
+                                        <pre class="mt-1 text-[10px] text-gray-400 whitespace-pre-wrap break-all">{raw.clone()}</pre>
+                                    }
+                                </div>
+                            }
+                        }).collect::<Html>()
+                    }
+                </div>
+            }
+        </div>
+    }
+}
@@ -0,0 +1,36 @@
+use yew::prelude::*;
+
+/// Offered in the picker. Anything passing `valid_reaction` is accepted from
+/// other clients, these are just the ones one click away.
+pub const EMOJI: &[&str] = &[
+    "👍", "👎", "❤️", "😂", "😮", "😢", "😡", "🎉", "🙏", "🔥", "👀", "✅", "🚀", "💯", "🤔", "👏",
+];
+
+#[derive(Properties, PartialEq)]
+pub struct EmojiPickerProps {
+    pub on_pick: Callback<String>,
+    pub on_close: Callback<()>,
+}
+
+/// A small popover grid of reaction emoji.
+#[function_component(EmojiPicker)]
+pub fn emoji_picker(props: &EmojiPickerProps) -> Html {
+    let onmouseleave = {
+        let on_close = props.on_close.clone();
+        Callback::from(move |_: MouseEvent| on_close.emit(()))
+    };
+
+    html! {
+        <div {onmouseleave} class="absolute z-20 mt-1 p-2 grid grid-cols-8 gap-1 rounded-xl bg-gray-900/95 border border-white/10 shadow-xl">
+            {
+                EMOJI.iter().map(|emoji| {
+                    let on_pick = props.on_pick.clone();
+                    let onclick = Callback::from(move |_: MouseEvent| on_pick.emit(emoji.to_string()));
+                    html! {
+                        <button {onclick} class="w-8 h-8 text-lg rounded-lg hover:bg-white/10">{*emoji}</button>
+                    }
+                }).collect::<Html>()
+            }
+        </div>
+    }
+}
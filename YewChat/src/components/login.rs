@@ -1,29 +1,158 @@
+use gloo_timers::callback::Timeout;
 use web_sys::HtmlInputElement;
 use yew::functional::*;
 use yew::prelude::*;
+use yew_agent::use_bridge;
 use yew_router::prelude::*;
+use yewchat_protocol::{validate_nick, ClientFrame, RegisterStatus, ServerFrame};
 
+use crate::services::{
+    connection::{ConnectionBus, ConnectionState},
+    event_bus::EventBus,
+    session,
+    websocket::{Handoff, WebsocketService},
+};
 use crate::Route;
 use crate::User;
 
+/// How long to wait for the server to answer a registration before giving up.
+const REGISTER_TIMEOUT_MS: u32 = 10_000;
+
+/// Where the nick typed in stands with the server.
+#[derive(Clone, PartialEq)]
+enum Status {
+    Idle,
+    /// Connecting and waiting for the server to accept the nick.
+    Checking,
+    Refused { reason: String, suggestions: Vec<String> },
+}
+
 #[function_component(Login)]
 pub fn login() -> Html {
-    let username = use_state(|| String::new());
     let user = use_context::<User>().expect("No context found.");
+    let username = use_state(String::new);
+    let status = {
+        let user = user.clone();
+        use_state(move || match user.signed_out.borrow_mut().take() {
+            Some(reason) => Status::Refused {
+                reason,
+                suggestions: vec![],
+            },
+            None => Status::Idle,
+        })
+    };
+    // The connection registering the nick, handed to the chat once it's accepted.
+    let pending = use_mut_ref(|| None::<WebsocketService>);
+    // Gives up on `pending` if the server never answers; replacing it cancels the old one.
+    let deadline = use_mut_ref(|| None::<Timeout>);
+    let handoff = use_context::<Handoff>().expect("No context found.");
+    let history = use_history().expect("Login is rendered inside the router");
+
+    let _events = {
+        let status = status.clone();
+        let pending = pending.clone();
+        use_bridge::<EventBus, _>(move |raw: String| {
+            if *status != Status::Checking {
+                return;
+            }
+            let refused = match ServerFrame::decode(&raw) {
+                Ok(ServerFrame::RegisterResult {
+                    status: RegisterStatus::Accepted,
+                    nick,
+                    author,
+                    ..
+                }) => {
+                    session::save(&nick);
+                    *user.username.borrow_mut() = nick;
+                    *user.author.borrow_mut() = author;
+                    if let Some(wss) = pending.borrow_mut().take() {
+                        handoff.put(wss);
+                    }
+                    history.push(Route::Chat);
+                    return;
+                }
+                Ok(ServerFrame::RegisterResult {
+                    status: RegisterStatus::Taken,
+                    nick,
+                    suggestions,
+                    ..
+                }) => (format!("{} is already taken", nick), suggestions),
+                Ok(ServerFrame::RegisterResult { message, suggestions, .. }) => (message, suggestions),
+                // Most likely a server too old or too new for us.
+                Ok(ServerFrame::Error { message, .. }) => (message, vec![]),
+                _ => return,
+            };
+            // Dropping the service closes its socket.
+            pending.borrow_mut().take();
+            status.set(Status::Refused {
+                reason: refused.0,
+                suggestions: refused.1,
+            });
+        })
+    };
+    let _connection = {
+        let status = status.clone();
+        let pending = pending.clone();
+        use_bridge::<ConnectionBus, _>(move |state: ConnectionState| {
+            if *status != Status::Checking {
+                return;
+            }
+            // The service would keep retrying an unreachable server forever; here
+            // the first failure is enough to let the user try again.
+            let reason = match state {
+                ConnectionState::Closed(reason) => format!("Can't reach the server: {}", reason),
+                ConnectionState::Reconnecting(_) => "Can't reach the server".to_string(),
+                _ => return,
+            };
+            pending.borrow_mut().take();
+            status.set(Status::Refused {
+                reason,
+                suggestions: vec![],
+            });
+        })
+    };
 
+    let submit = {
+        let status = status.clone();
+        move |nick: &str| {
+            let Ok(nick) = validate_nick(nick) else {
+                return;
+            };
+            status.set(Status::Checking);
+            *pending.borrow_mut() = Some(WebsocketService::new(ClientFrame::register(nick, &session::token())));
+            let (pending, status) = (pending.clone(), status.clone());
+            *deadline.borrow_mut() = Some(Timeout::new(REGISTER_TIMEOUT_MS, move || {
+                if pending.borrow_mut().take().is_some() {
+                    status.set(Status::Refused {
+                        reason: "Can't reach the server: no answer".into(),
+                        suggestions: vec![],
+                    });
+                }
+            }));
+        }
+    };
     let oninput = {
         let current_username = username.clone();
+        let status = status.clone();
         Callback::from(move |e: InputEvent| {
             let input: HtmlInputElement = e.target_unchecked_into();
             current_username.set(input.value());
+            if matches!(*status, Status::Refused { .. }) {
+                status.set(Status::Idle);
+            }
         })
     };
-
-    let onclick = {
+    let onsubmit = {
         let username = username.clone();
-        let user = user.clone();
-        Callback::from(move |_| *user.username.borrow_mut() = (*username).clone())
+        let submit = submit.clone();
+        Callback::from(move |e: FocusEvent| {
+            e.prevent_default();
+            submit(&username);
+        })
     };
+    let checking = *status == Status::Checking;
+    // Explained as soon as something is typed; an empty box just keeps the button off.
+    let invalid = validate_nick(&username).err();
 
     html! {
         <div class="w-screen h-screen bg-gradient-to-br from-purple-900 via-blue-900 to-indigo-900 flex items-center justify-center p-4 overflow-hidden">
@@ -46,7 +175,7 @@ pub fn login() -> Html {
                         <p class="text-gray-300">{"Enter your username to start chatting"}</p>
                     </div>
 
-                    <form class="space-y-6">
+                    <form {onsubmit} class="space-y-6">
                         <div class="relative">
                             <div class="absolute inset-y-0 left-0 pl-4 flex items-center pointer-events-none">
                                 <svg class="h-5 w-5 text-gray-400" fill="none" stroke="currentColor" viewBox="0 0 24 24">
@@ -55,24 +184,51 @@ pub fn login() -> Html {
                             </div>
                             <input
                                 {oninput}
+                                value={(*username).clone()}
+                                disabled={checking}
                                 class="w-full pl-12 pr-4 py-5 bg-white/10 border border-white/20 rounded-xl text-white placeholder-gray-400 text-center focus:outline-none focus:ring-2 focus:ring-purple-500 focus:border-transparent backdrop-blur-sm transition-all duration-300 hover:bg-white/20 text-lg"
                                 placeholder="Enter your username"
                                 type="text"
                             />
                         </div>
+                        if let Some(error) = invalid.filter(|_| !username.is_empty()) {
+                            <p class="-mt-4 text-sm text-center text-amber-300">{error.to_string()}</p>
+                        }
 
-                        <Link<Route> to={Route::Chat}>
-                            <button
-                                {onclick}
-                                disabled={username.len() < 1}
-                                class="mt-4 w-full py-3 px-4 bg-gradient-to-r from-purple-600 to-blue-600 hover:from-purple-700 hover:to-blue-700 disabled:from-gray-600 disabled:to-gray-700 disabled:opacity-50 disabled:cursor-not-allowed text-white font-semibold rounded-xl shadow-lg hover:shadow-xl transform hover:scale-105 disabled:hover:scale-100 transition-all duration-300 flex items-center justify-center space-x-2 text-base"
-                            >
-                                <span>{"Start Chatting"}</span>
-                                <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
-                                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M13 7l5 5m0 0l-5 5m5-5H6"></path>
-                                </svg>
-                            </button>
-                        </Link<Route>>
+                        if let Status::Refused { reason, suggestions } = &*status {
+                            <div class="px-4 py-3 rounded-xl bg-red-500/10 border border-red-400/30 text-sm text-red-200">
+                                <p>{reason.clone()}</p>
+                                if !suggestions.is_empty() {
+                                    <div class="flex flex-wrap items-center gap-2 mt-2">
+                                        <span class="text-gray-300">{"Try:"}</span>
+                                        {
+                                            for suggestions.iter().map(|nick| {
+                                                let onclick = {
+                                                    let (nick, username, submit) = (nick.clone(), username.clone(), submit.clone());
+                                                    Callback::from(move |_: MouseEvent| {
+                                                        username.set(nick.clone());
+                                                        submit(&nick);
+                                                    })
+                                                };
+                                                html! {
+                                                    <button type="button" {onclick} class="px-3 py-1 rounded-full bg-white/10 border border-white/20 text-white hover:bg-white/20">{nick.clone()}</button>
+                                                }
+                                            })
+                                        }
+                                    </div>
+                                }
+                            </div>
+                        }
+                        <button
+                            type="submit"
+                            disabled={invalid.is_some() || checking}
+                            class="mt-4 w-full py-3 px-4 bg-gradient-to-r from-purple-600 to-blue-600 hover:from-purple-700 hover:to-blue-700 disabled:from-gray-600 disabled:to-gray-700 disabled:opacity-50 disabled:cursor-not-allowed text-white font-semibold rounded-xl shadow-lg hover:shadow-xl transform hover:scale-105 disabled:hover:scale-100 transition-all duration-300 flex items-center justify-center space-x-2 text-base"
+                        >
+                            <span>{if checking { "Checking nickname..." } else { "Start Chatting" }}</span>
+                            <svg class="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M13 7l5 5m0 0l-5 5m5-5H6"></path>
+                            </svg>
+                        </button>
                     </form>
 
                     <div class="mt-8 text-center">
@@ -80,6 +236,9 @@ pub fn login() -> Html {
                             {"Ready to connect with others? "}
                             <span class="text-purple-400 font-medium">{"Let's get started!"}</span>
                         </p>
+                        <Link<Route> to={Route::Settings} classes="inline-block mt-2 text-xs text-gray-500 hover:text-gray-300">
+                            {"Server settings"}
+                        </Link<Route>>
                     </div>
                 </div>
 
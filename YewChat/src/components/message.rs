@@ -0,0 +1,496 @@
+use std::rc::Rc;
+
+use web_sys::HtmlTextAreaElement;
+use yew::functional::*;
+use yew::prelude::*;
+use yewchat_protocol::{ChatMessage, MessageId, MAX_MESSAGE_LEN};
+
+use crate::markdown;
+use crate::media::{self, Media, MediaKind};
+use crate::services::endpoint;
+use crate::components::{
+    composer::fit,
+    emoji_picker::EmojiPicker,
+    timestamp::{absolute, millis, Timestamp},
+};
+
+/// Where one of our own messages is on its way to the server.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Delivery {
+    Sending,
+    Delivered,
+    Failed(String),
+}
+
+#[derive(Properties, PartialEq)]
+pub struct MessageBubbleProps {
+    pub message: ChatMessage,
+    /// The current user's nick.
+    pub me: String,
+    /// Sent by the current user, who may edit or delete it.
+    pub is_own: bool,
+    pub avatar: String,
+    /// Whether the sender is still connected.
+    pub online: bool,
+    pub now: f64,
+    pub on_edit: Callback<(MessageId, String)>,
+    pub on_delete: Callback<MessageId>,
+    /// Adds (`true`) or removes (`false`) our reaction.
+    pub on_react: Callback<(MessageId, String, bool)>,
+    /// The message this one replies to, if it is loaded.
+    #[prop_or_default]
+    pub parent: Option<ChatMessage>,
+    /// How many replies the thread started by this message has.
+    #[prop_or_default]
+    pub replies: usize,
+    /// Opens the thread this message belongs to. Left out inside the thread panel,
+    /// which hides the quote and reply links.
+    #[prop_or_default]
+    pub on_thread: Option<Callback<MessageId>>,
+    /// Shown under our own messages. Until `Delivered` the message isn't stored
+    /// yet, so it can't be edited, deleted or reacted to.
+    #[prop_or_default]
+    pub delivery: Option<Delivery>,
+    #[prop_or_default]
+    pub on_retry: Callback<()>,
+    #[prop_or_default]
+    pub on_discard: Callback<()>,
+    /// Where attachments are downloaded from, see [`endpoint::files_base`].
+    #[prop_or_default]
+    pub files_base: String,
+}
+
+/// First line of a quoted parent, cut short.
+fn preview(text: &str) -> String {
+    const MAX: usize = 80;
+    let line = text.lines().next().unwrap_or_default();
+    if line.chars().count() > MAX || line.len() < text.len() {
+        format!("{}...", line.chars().take(MAX).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+/// "12 KB", "3.4 MB" and so on, for file sizes.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KB", "MB", "GB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if size < 10.0 {
+        format!("{:.1} {}", size, UNITS[unit])
+    } else {
+        format!("{:.0} {}", size, UNITS[unit])
+    }
+}
+
+/// Inline player or picture for a linked file. The URL has passed `safe_url`.
+fn media_preview(item: &Media) -> Html {
+    let url = item.url.clone();
+    match item.kind {
+        MediaKind::Image => html! {
+            <a href={url.clone()} target="_blank" rel="noopener noreferrer">
+                <img class="rounded-lg max-w-xs max-h-80 shadow-lg" src={url} loading="lazy" alt="image"/>
+            </a>
+        },
+        MediaKind::Video => html! {
+            <video class="rounded-lg max-w-sm max-h-80 shadow-lg" src={url} controls=true preload="metadata"/>
+        },
+        MediaKind::Audio => html! {
+            <audio class="w-72" src={url} controls=true preload="none"/>
+        },
+    }
+}
+
+/// The text of an edit in progress. Kept in state rather than read back from the
+/// props, which change on every clock tick and incoming message, so re-renders
+/// don't reset what has been typed.
+#[derive(Default, PartialEq)]
+struct EditDraft(Option<String>);
+
+enum EditAction {
+    /// Starts editing from the message's current text.
+    Start(String),
+    Input(String),
+    Stop,
+}
+
+impl Reducible for EditDraft {
+    type Action = EditAction;
+
+    fn reduce(self: Rc<Self>, action: EditAction) -> Rc<Self> {
+        match action {
+            // Already editing: keep what was typed.
+            EditAction::Start(_) if self.0.is_some() => self,
+            EditAction::Start(text) | EditAction::Input(text) => Rc::new(EditDraft(Some(text))),
+            EditAction::Stop => Rc::new(EditDraft(None)),
+        }
+    }
+}
+
+/// One message with its avatar and reactions. Right-clicking one of our own
+/// messages offers to edit it in place or delete it.
+#[function_component(MessageBubble)]
+pub fn message_bubble(props: &MessageBubbleProps) -> Html {
+    let menu_open = use_state(|| false);
+    let edit = use_reducer(EditDraft::default);
+    let picking = use_state(|| false);
+    let media_hidden = use_state(|| false);
+    let draft_ref = use_node_ref();
+
+    let m = &props.message;
+    let is_own = props.is_own;
+    let pending = matches!(props.delivery, Some(Delivery::Sending | Delivery::Failed(_)));
+    let can_change = is_own && !m.deleted && !pending;
+
+    let open_menu = {
+        let menu_open = menu_open.clone();
+        Callback::from(move |e: MouseEvent| {
+            if can_change {
+                e.prevent_default();
+                menu_open.set(true);
+            }
+        })
+    };
+    let close_menu = {
+        let menu_open = menu_open.clone();
+        Callback::from(move |_: MouseEvent| menu_open.set(false))
+    };
+    let start_edit = {
+        let menu_open = menu_open.clone();
+        let edit = edit.clone();
+        let text = m.text.clone();
+        Callback::from(move |_: MouseEvent| {
+            menu_open.set(false);
+            edit.dispatch(EditAction::Start(text.clone()));
+        })
+    };
+    let delete = {
+        let menu_open = menu_open.clone();
+        let on_delete = props.on_delete.clone();
+        let id = m.id;
+        Callback::from(move |_: MouseEvent| {
+            menu_open.set(false);
+            let confirmed = gloo_utils::window()
+                .confirm_with_message("Delete this message for everyone?")
+                .unwrap_or(false);
+            if confirmed {
+                on_delete.emit(id);
+            }
+        })
+    };
+    let save = {
+        let edit = edit.clone();
+        let on_edit = props.on_edit.clone();
+        let (id, original) = (m.id, m.text.clone());
+        Callback::from(move |_: ()| {
+            if let Some(draft) = &edit.0 {
+                let text = draft.trim().to_string();
+                if !text.is_empty() && text != original {
+                    on_edit.emit((id, text));
+                }
+            }
+            edit.dispatch(EditAction::Stop);
+        })
+    };
+    let cancel = {
+        let edit = edit.clone();
+        Callback::from(move |_: MouseEvent| edit.dispatch(EditAction::Stop))
+    };
+    let onkeydown = {
+        let edit = edit.clone();
+        let save = save.clone();
+        // Like the message box: Enter saves, Shift+Enter starts a new line.
+        Callback::from(move |e: KeyboardEvent| match e.key().as_str() {
+            "Enter" if !e.shift_key() && !e.is_composing() => {
+                e.prevent_default();
+                save.emit(());
+            }
+            "Escape" => edit.dispatch(EditAction::Stop),
+            _ => {}
+        })
+    };
+    let oninput = {
+        let edit = edit.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlTextAreaElement = e.target_unchecked_into();
+            fit(&input);
+            edit.dispatch(EditAction::Input(input.value()));
+        })
+    };
+    let editing = edit.0.is_some();
+    {
+        let draft_ref = draft_ref.clone();
+        use_effect_with_deps(
+            move |editing| {
+                if let Some(input) = draft_ref.cast::<HtmlTextAreaElement>().filter(|_| *editing) {
+                    fit(&input);
+                    let _ = input.focus();
+                }
+                || ()
+            },
+            editing,
+        );
+    }
+
+    let open_picker = {
+        let picking = picking.clone();
+        Callback::from(move |_: MouseEvent| picking.set(true))
+    };
+    let close_picker = {
+        let picking = picking.clone();
+        Callback::from(move |_: ()| picking.set(false))
+    };
+    let pick = {
+        let picking = picking.clone();
+        let on_react = props.on_react.clone();
+        let reactions = m.reactions.clone();
+        let (id, me) = (m.id, props.me.clone());
+        Callback::from(move |emoji: String| {
+            picking.set(false);
+            let mine = reactions.get(&emoji).is_some_and(|who| who.contains(&me));
+            on_react.emit((id, emoji, !mine));
+        })
+    };
+    let reaction_bar = m
+        .reactions
+        .iter()
+        .filter(|(_, who)| !who.is_empty())
+        .map(|(emoji, who)| {
+            let mine = who.contains(&props.me);
+            let onclick = {
+                let on_react = props.on_react.clone();
+                let (id, emoji) = (m.id, emoji.clone());
+                Callback::from(move |_: MouseEvent| on_react.emit((id, emoji.clone(), !mine)))
+            };
+            let style = if mine {
+                "bg-purple-500/30 border-purple-400/60"
+            } else {
+                "bg-white/5 border-white/10 hover:bg-white/10"
+            };
+            html! {
+                <button {onclick} title={format!("{} reacted with {}", who.join(", "), emoji)} class={format!("px-2 py-0.5 rounded-full border text-sm text-white {}", style)}>
+                    {format!("{} {}", emoji, who.len())}
+                </button>
+            }
+        })
+        .collect::<Html>();
+
+    let file = m.attachment.as_ref().filter(|_| !m.deleted).map(|a| (a, endpoint::file_url(&props.files_base, a)));
+    let mut attachments = if m.deleted { vec![] } else { media::media(&m.text) };
+    if let Some((a, url)) = &file {
+        if let Some(kind) = media::from_mime(&a.mime) {
+            attachments.insert(0, Media { url: url.clone(), kind });
+        }
+    }
+    let toggle_media = {
+        let media_hidden = media_hidden.clone();
+        Callback::from(move |_: MouseEvent| media_hidden.set(!*media_hidden))
+    };
+
+    // Replies to a reply land in the same thread.
+    let root = m.reply_to.unwrap_or(m.id);
+    let open_thread = props.on_thread.clone().filter(|_| !pending).map(|on_thread| Callback::from(move |_: MouseEvent| on_thread.emit(root)));
+    let quote = match (&open_thread, m.reply_to) {
+        (Some(onclick), Some(_)) => {
+            let (author, text) = match &props.parent {
+                Some(parent) if parent.deleted => (parent.from.clone(), "This message was deleted".to_string()),
+                Some(parent) => (parent.from.clone(), preview(&parent.text)),
+                None => (String::new(), "Original message isn't loaded".to_string()),
+            };
+            html! {
+                <button onclick={onclick.clone()} class="block w-full mb-2 pl-3 border-l-2 border-purple-400/60 text-left text-xs text-gray-300 hover:text-white">
+                    if !author.is_empty() {
+                        <span class="font-semibold">{author}</span>{" "}
+                    }
+                    {text}
+                </button>
+            }
+        }
+        _ => html! {},
+    };
+
+    let container = if is_own { "flex justify-end" } else { "flex justify-start" };
+    let mentions_me = !is_own && !m.deleted && m.mentions.contains(&props.me);
+    let bubble_color = if is_own {
+        "bg-green-500/20 border-green-400/30"
+    } else if mentions_me {
+        "bg-amber-500/20 border-amber-400/60 ring-2 ring-amber-400/40"
+    } else {
+        "bg-white/10 border-white/10"
+    };
+    let bubble_rounding = if is_own {
+        "rounded-2xl rounded-tr-none"
+    } else {
+        "rounded-2xl rounded-tl-none"
+    };
+    let name_color = if is_own { "text-green-300" } else { "text-purple-300" };
+    let avatar = html! {
+        <img class="w-10 h-10 rounded-full ring-2 ring-purple-500/30 shadow-lg flex-shrink-0" src={props.avatar.clone()} alt="avatar"/>
+    };
+
+    html! {
+        <div class={container}>
+            <div class="flex items-start space-x-3 max-w-4xl">
+                if !is_own {
+                    {avatar.clone()}
+                }
+                <div class={classes!("relative", "flex", "flex-col", if is_own { "items-end" } else { "items-start" })}>
+                    <div oncontextmenu={open_menu} class={classes!("backdrop-blur-sm", "p-4", "border", "shadow-lg", bubble_color, bubble_rounding, matches!(props.delivery, Some(Delivery::Sending)).then_some("opacity-70"))}>
+                        <div class="flex items-center space-x-2 mb-2">
+                            <span class={format!("text-sm font-semibold {}", name_color)}>
+                                {m.from.clone()}
+                                if is_own {
+                                    {" (You)"}
+                                }
+                            </span>
+                            if !props.online {
+                                <span class="text-xs text-gray-500 italic">{"offline"}</span>
+                            }
+                            if mentions_me {
+                                <span class="px-1.5 rounded bg-amber-500/30 text-xs text-amber-200">{"mentioned you"}</span>
+                            }
+                            <Timestamp time={m.time} now={props.now} />
+                            if let Some(edited) = m.edited.filter(|_| !m.deleted) {
+                                <span class="text-xs text-gray-500" title={absolute(millis(edited))}>{"(edited)"}</span>
+                            }
+                        </div>
+                        {quote}
+                        <div class="text-white">
+                            if m.deleted {
+                                <p class="italic text-gray-400">{"This message was deleted"}</p>
+                            } else if let Some(draft) = edit.0.clone() {
+                                <div class="flex items-end space-x-2">
+                                    <textarea
+                                        ref={draft_ref}
+                                        {onkeydown}
+                                        {oninput}
+                                        value={draft}
+                                        rows="1"
+                                        maxlength={MAX_MESSAGE_LEN.to_string()}
+                                        class="flex-grow px-3 py-1 resize-none overflow-y-auto bg-white/10 border border-white/20 rounded-lg text-white focus:outline-none focus:ring-2 focus:ring-purple-500"
+                                    />
+                                    <button onclick={move |_| save.emit(())} class="text-xs text-green-300 hover:text-green-200">{"Save"}</button>
+                                    <button onclick={cancel} class="text-xs text-gray-400 hover:text-gray-300">{"Cancel"}</button>
+                                </div>
+                            } else if m.emote {
+                                <div class="flex items-baseline space-x-1 italic text-purple-100">
+                                    <span class="flex-shrink-0">{format!("* {}", m.from)}</span>
+                                    <div class="space-y-2">{markdown::render(&m.text)}</div>
+                                </div>
+                            } else {
+                                <div class="space-y-2">{markdown::render(&m.text)}</div>
+                            }
+                        </div>
+                        if let Some((a, url)) = file {
+                            <a href={url} download={a.name.clone()} target="_blank" rel="noopener noreferrer" class="flex items-center space-x-3 mt-2 px-3 py-2 rounded-lg bg-white/5 border border-white/10 hover:bg-white/10">
+                                <svg class="w-6 h-6 text-purple-300 flex-shrink-0" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                                    <path stroke-linecap="round" stroke-linejoin="round" stroke-width="2" d="M4 16v1a3 3 0 003 3h10a3 3 0 003-3v-1m-4-4l-4 4m0 0l-4-4m4 4V4"></path>
+                                </svg>
+                                <div class="min-w-0">
+                                    <div class="text-sm text-white truncate">{a.name.clone()}</div>
+                                    <div class="text-xs text-gray-400">{format_size(a.size)}</div>
+                                </div>
+                            </a>
+                        }
+                        if !attachments.is_empty() {
+                            <div class="mt-2">
+                                <button onclick={toggle_media} class="text-xs text-gray-400 hover:text-gray-200">
+                                    {match (*media_hidden, attachments.len()) {
+                                        (true, 1) => "Show media".to_string(),
+                                        (true, n) => format!("Show media ({})", n),
+                                        (false, _) => "Hide media".to_string(),
+                                    }}
+                                </button>
+                                if !*media_hidden {
+                                    <div class="flex flex-col gap-2 mt-2">
+                                        { for attachments.iter().map(media_preview) }
+                                    </div>
+                                }
+                            </div>
+                        }
+                    </div>
+                    if let Some(delivery) = &props.delivery {
+                        <div class="flex items-center space-x-2 mt-1 text-xs">
+                            {
+                                match delivery {
+                                    Delivery::Sending => html! { <span class="text-gray-400 italic">{"Sending..."}</span> },
+                                    Delivery::Delivered => html! { <span class="text-gray-400">{"✓ Delivered"}</span> },
+                                    Delivery::Failed(reason) => {
+                                        let retry = props.on_retry.reform(|_: MouseEvent| ());
+                                        let discard = props.on_discard.reform(|_: MouseEvent| ());
+                                        html! {
+                                            <>
+                                                <span class="text-red-300">{format!("Not sent: {}", reason)}</span>
+                                                <button onclick={retry} class="text-purple-300 hover:text-purple-200">{"Retry"}</button>
+                                                <button onclick={discard} class="text-gray-400 hover:text-gray-300">{"Discard"}</button>
+                                            </>
+                                        }
+                                    }
+                                }
+                            }
+                        </div>
+                    }
+                    if !m.deleted && !pending {
+                        <div class="relative flex flex-wrap items-center gap-1 mt-1">
+                            {reaction_bar}
+                            <button onclick={open_picker} title="Add reaction" class="px-2 py-0.5 rounded-full text-sm text-gray-400 hover:text-white hover:bg-white/10">{"+"}</button>
+                            if let Some(onclick) = open_thread {
+                                <button {onclick} class="px-2 py-0.5 rounded-full text-xs text-purple-300 hover:text-purple-200 hover:bg-white/10">
+                                    {match props.replies {
+                                        0 => "Reply".to_string(),
+                                        1 => "1 reply".to_string(),
+                                        n => format!("{} replies", n),
+                                    }}
+                                </button>
+                            }
+                            if *picking {
+                                <div class={classes!("absolute", "top-full", if is_own { "right-0" } else { "left-0" })}>
+                                    <EmojiPicker on_pick={pick} on_close={close_picker} />
+                                </div>
+                            }
+                        </div>
+                    }
+                    if *menu_open {
+                        <div onmouseleave={close_menu} class="absolute top-full right-0 z-20 mt-1 w-32 py-1 rounded-lg bg-gray-900/95 border border-white/10 shadow-xl">
+                            <button onclick={start_edit} class="block w-full px-3 py-1.5 text-left text-sm text-gray-200 hover:bg-white/10">{"Edit"}</button>
+                            <button onclick={delete} class="block w-full px-3 py-1.5 text-left text-sm text-red-300 hover:bg-white/10">{"Delete"}</button>
+                        </div>
+                    }
+                </div>
+                if is_own {
+                    {avatar}
+                }
+            </div>
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(draft: Rc<EditDraft>, actions: Vec<EditAction>) -> Rc<EditDraft> {
+        actions.into_iter().fold(draft, Reducible::reduce)
+    }
+
+    #[test]
+    fn edit_draft_follows_typing_and_survives_rerenders() {
+        let draft = apply(
+            Rc::default(),
+            vec![EditAction::Start("hello".into()), EditAction::Input("hello\nwor".into())],
+        );
+        assert_eq!(draft.0.as_deref(), Some("hello\nwor"));
+        // A re-render for new props (the clock, a reaction, the original text being
+        // updated) dispatches nothing, and starting again doesn't reseed it either.
+        let draft = apply(draft, vec![EditAction::Start("hello (updated)".into())]);
+        assert_eq!(draft.0.as_deref(), Some("hello\nwor"));
+        assert_eq!(apply(draft, vec![EditAction::Stop]).0, None);
+    }
+}
@@ -1,2 +1,10 @@
+pub mod channel_list;
 pub mod chat;
-pub mod login;
\ No newline at end of file
+pub mod composer;
+pub mod diagnostics;
+pub mod emoji_picker;
+pub mod login;
+pub mod message;
+pub mod settings;
+pub mod thread;
+pub mod timestamp;
\ No newline at end of file
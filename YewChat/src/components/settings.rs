@@ -0,0 +1,111 @@
+use web_sys::HtmlInputElement;
+use yew::functional::*;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+use crate::services::endpoint::{self, Source};
+use crate::Route;
+
+#[function_component(Settings)]
+pub fn settings() -> Html {
+    let current = use_state(endpoint::resolve);
+    let server = use_state(|| endpoint::stored_override().unwrap_or_default());
+    let error = use_state(|| Option::<String>::None);
+
+    let oninput = {
+        let server = server.clone();
+        let error = error.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            server.set(input.value());
+            error.set(None);
+        })
+    };
+
+    let onsave = {
+        let server = server.clone();
+        let current = current.clone();
+        let error = error.clone();
+        Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+            match endpoint::set_override(&server) {
+                Ok(url) => {
+                    server.set(url);
+                    current.set(endpoint::resolve());
+                }
+                Err(e) => error.set(Some(e)),
+            }
+        })
+    };
+
+    let onreset = {
+        let server = server.clone();
+        let current = current.clone();
+        let error = error.clone();
+        Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+            endpoint::clear_override();
+            server.set(String::new());
+            error.set(None);
+            current.set(endpoint::resolve());
+        })
+    };
+
+    html! {
+        <div class="w-screen h-screen bg-gradient-to-br from-purple-900 via-blue-900 to-indigo-900 flex items-center justify-center p-4 overflow-hidden">
+            <div class="absolute inset-0 bg-black opacity-50"></div>
+
+            <div class="relative z-10 w-full max-w-lg">
+                <div class="bg-white/10 backdrop-blur-lg rounded-3xl p-8 shadow-2xl border border-white/20">
+                    <div class="text-center mb-8">
+                        <h1 class="text-3xl font-bold text-white mb-2">{"Server Settings"}</h1>
+                        <p class="text-gray-300">{"Choose which chat server to connect to"}</p>
+                    </div>
+
+                    <div class="mb-6 p-4 bg-white/5 rounded-xl border border-white/10">
+                        <p class="text-sm text-gray-400">{"Currently using"}</p>
+                        <p class="text-white font-mono break-all">{current.url.clone()}</p>
+                        <p class="text-xs text-gray-400 mt-1">{current.source.describe()}</p>
+                        if current.source == Source::Query {
+                            <p class="text-xs text-yellow-300 mt-1">{"The page URL takes precedence over the override below."}</p>
+                        }
+                    </div>
+
+                    <form class="space-y-4">
+                        <input
+                            {oninput}
+                            value={(*server).clone()}
+                            class="w-full px-4 py-4 bg-white/10 border border-white/20 rounded-xl text-white placeholder-gray-400 font-mono focus:outline-none focus:ring-2 focus:ring-purple-500 focus:border-transparent backdrop-blur-sm transition-all duration-300 hover:bg-white/20"
+                            placeholder="wss://chat.example.com"
+                            type="text"
+                        />
+                        if let Some(e) = &*error {
+                            <p class="text-sm text-red-300">{e.clone()}</p>
+                        }
+                        <div class="flex space-x-3">
+                            <button
+                                onclick={onsave}
+                                disabled={server.trim().is_empty()}
+                                class="flex-grow py-3 px-4 bg-gradient-to-r from-purple-600 to-blue-600 hover:from-purple-700 hover:to-blue-700 disabled:from-gray-600 disabled:to-gray-700 disabled:opacity-50 disabled:cursor-not-allowed text-white font-semibold rounded-xl shadow-lg transition-all duration-300"
+                            >
+                                {"Save"}
+                            </button>
+                            <button
+                                onclick={onreset}
+                                class="py-3 px-4 bg-white/10 hover:bg-white/20 text-white rounded-xl border border-white/20 transition-all duration-300"
+                            >
+                                {"Use default"}
+                            </button>
+                        </div>
+                    </form>
+
+                    <div class="mt-8 text-center">
+                        <Link<Route> to={Route::Login} classes="text-sm text-purple-400 hover:text-purple-300 font-medium">
+                            {"Back to login"}
+                        </Link<Route>>
+                    </div>
+                </div>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,75 @@
+use web_sys::HtmlInputElement;
+use yew::functional::*;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct ThreadPanelProps {
+    /// The parent message followed by its replies, already rendered.
+    pub children: Children,
+    pub replies: usize,
+    /// Replies can only be sent while connected.
+    pub online: bool,
+    pub on_reply: Callback<String>,
+    pub on_close: Callback<()>,
+}
+
+/// Side panel showing one thread, with its own box for replying in it.
+#[function_component(ThreadPanel)]
+pub fn thread_panel(props: &ThreadPanelProps) -> Html {
+    let draft = use_state(String::new);
+
+    let oninput = {
+        let draft = draft.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            draft.set(input.value());
+        })
+    };
+    let onsubmit = {
+        let draft = draft.clone();
+        let on_reply = props.on_reply.clone();
+        Callback::from(move |e: FocusEvent| {
+            e.prevent_default();
+            let text = draft.trim().to_string();
+            if !text.is_empty() {
+                on_reply.emit(text);
+                draft.set(String::new());
+            }
+        })
+    };
+    let onclose = {
+        let on_close = props.on_close.clone();
+        Callback::from(move |_: MouseEvent| on_close.emit(()))
+    };
+
+    html! {
+        <div class="relative z-10 flex-none w-96 h-full flex flex-col bg-white/5 backdrop-blur-lg border-l border-white/10">
+            <div class="flex items-center justify-between p-6 border-b border-white/10">
+                <div>
+                    <h2 class="text-xl font-bold text-white">{"Thread"}</h2>
+                    <p class="text-sm text-gray-300">
+                        {match props.replies {
+                            0 => "No replies yet".to_string(),
+                            1 => "1 reply".to_string(),
+                            n => format!("{} replies", n),
+                        }}
+                    </p>
+                </div>
+                <button onclick={onclose} title="Close thread" class="p-2 rounded-lg text-gray-400 hover:text-white hover:bg-white/10">{"×"}</button>
+            </div>
+            <div class="flex-grow overflow-y-auto p-4 space-y-4">
+                { for props.children.iter() }
+            </div>
+            <form {onsubmit} class="p-4 border-t border-white/10">
+                <input
+                    {oninput}
+                    value={(*draft).clone()}
+                    disabled={!props.online}
+                    class="w-full px-4 py-3 bg-white/10 border border-white/20 rounded-xl text-white placeholder-gray-400 focus:outline-none focus:ring-2 focus:ring-purple-500 disabled:opacity-50"
+                    placeholder="Reply in thread..."
+                    type="text"
+                />
+            </form>
+        </div>
+    }
+}
@@ -0,0 +1,112 @@
+use js_sys::{Date, JsString};
+use wasm_bindgen::JsValue;
+use yew::prelude::*;
+use yewchat_protocol::Timestamp as WireTimestamp;
+
+const MINUTE: f64 = 60_000.0;
+const HOUR: f64 = 60.0 * MINUTE;
+const DAY: f64 = 24.0 * HOUR;
+
+/// "just now", "5 min ago", "3 hours ago"... falls back to the date after a week.
+pub fn relative(now: f64, then: f64) -> String {
+    let elapsed = (now - then).max(0.0);
+    if elapsed < MINUTE {
+        return "just now".into();
+    }
+    let plural = |n: u64, unit: &str| format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" });
+    if elapsed < HOUR {
+        format!("{} min ago", (elapsed / MINUTE) as u64)
+    } else if elapsed < DAY {
+        plural((elapsed / HOUR) as u64, "hour")
+    } else if elapsed < 7.0 * DAY {
+        plural((elapsed / DAY) as u64, "day")
+    } else {
+        show(then, |d| d.to_locale_date_string(&locale(), &JsValue::UNDEFINED))
+    }
+}
+
+/// Full date and time in the browser's locale, for tooltips.
+pub fn absolute(time: f64) -> String {
+    show(time, |d| d.to_locale_string(&locale(), &JsValue::UNDEFINED))
+}
+
+/// Heading for the day separator above the first message of each day.
+pub fn day_label(now: f64, time: f64) -> String {
+    if same_day(now, time) {
+        "Today".into()
+    } else if same_day(now - DAY, time) {
+        "Yesterday".into()
+    } else {
+        show(time, |d| d.to_locale_date_string(&locale(), &JsValue::UNDEFINED))
+    }
+}
+
+/// Whether both instants fall on the same calendar day in local time.
+pub fn same_day(a: f64, b: f64) -> bool {
+    match (date(a), date(b)) {
+        (Some(a), Some(b)) => {
+            a.get_full_year() == b.get_full_year() && a.get_month() == b.get_month() && a.get_date() == b.get_date()
+        }
+        _ => false,
+    }
+}
+
+pub fn millis(time: WireTimestamp) -> f64 {
+    time.millis() as f64
+}
+
+/// `None` for a time out of `Date`'s range, which would throw once formatted.
+fn date(time: f64) -> Option<Date> {
+    let date = Date::new(&JsValue::from_f64(time));
+    (!date.get_time().is_nan()).then_some(date)
+}
+
+/// `time` formatted with `format`, or the bare number if no `Date` can hold it.
+fn show(time: f64, format: impl FnOnce(&Date) -> JsString) -> String {
+    date(time).map(|d| format(&d).into()).unwrap_or_else(|| time.to_string())
+}
+
+fn locale() -> String {
+    gloo_utils::window()
+        .navigator()
+        .language()
+        .unwrap_or_else(|| "en-US".into())
+}
+
+#[derive(Properties, PartialEq)]
+pub struct TimestampProps {
+    pub time: WireTimestamp,
+    /// Current time, passed down so every timestamp refreshes on the same tick.
+    pub now: f64,
+}
+
+#[function_component(Timestamp)]
+pub fn timestamp(props: &TimestampProps) -> Html {
+    let time = millis(props.time);
+    let iso = show(time, Date::to_iso_string);
+
+    html! {
+        <time class="text-xs text-gray-400" datetime={iso} title={absolute(time)}>
+            {relative(props.now, time)}
+        </time>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct DaySeparatorProps {
+    pub time: WireTimestamp,
+    pub now: f64,
+}
+
+#[function_component(DaySeparator)]
+pub fn day_separator(props: &DaySeparatorProps) -> Html {
+    html! {
+        <div class="flex items-center space-x-4 py-2">
+            <div class="flex-grow border-t border-white/10"></div>
+            <span class="text-xs font-medium text-gray-400 uppercase tracking-wide">
+                {day_label(props.now, millis(props.time))}
+            </span>
+            <div class="flex-grow border-t border-white/10"></div>
+        </div>
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::{BTreeSet, HashMap};
+
+/// Everyone seen during this session, including people who have since left.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Participant {
+    pub name: String,
+    pub avatar: String,
+    /// Milliseconds since the Unix epoch.
+    pub first_seen: f64,
+    pub last_seen: f64,
+    pub online: bool,
+}
+
+impl Participant {
+    fn new(name: &str, now: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            avatar: avatar_url(name),
+            first_seen: now,
+            last_seen: now,
+            online: false,
+        }
+    }
+}
+
+pub fn avatar_url(name: &str) -> String {
+    format!("https://api.dicebear.com/8.x/adventurer-neutral/svg?seed={}", name)
+}
+
+/// Remembers every participant so history stays renderable after they disconnect.
+#[derive(Default)]
+pub struct Directory {
+    participants: HashMap<String, Participant>,
+}
+
+impl Directory {
+    /// Applies a `users` broadcast: listed names are online, everyone else is not.
+    pub fn set_online(&mut self, names: &[String], now: f64) {
+        for p in self.participants.values_mut() {
+            if p.online {
+                // They were here until this broadcast.
+                p.last_seen = now;
+            }
+            p.online = false;
+        }
+        for name in names {
+            let p = self
+                .participants
+                .entry(name.clone())
+                .or_insert_with(|| Participant::new(name, now));
+            p.online = true;
+            p.last_seen = now;
+        }
+    }
+
+    /// Records activity from `name`, adding them if this is the first we hear of them.
+    pub fn saw(&mut self, name: &str, now: f64) {
+        self.participants
+            .entry(name.to_string())
+            .or_insert_with(|| Participant::new(name, now))
+            .last_seen = now;
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Participant> {
+        self.participants.get(name)
+    }
+
+    /// Every name seen this session, alphabetically.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.participants.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// `members` followed by anyone else who appears in `others`, each group alphabetically.
+    ///
+    /// Everyone mentioned is looked up, so people who left still show with their avatar.
+    pub fn roster<'a>(&'a self, members: &'a [String], others: impl IntoIterator<Item = &'a str>) -> Vec<&'a Participant> {
+        let mut names: BTreeSet<&str> = members.iter().map(String::as_str).collect();
+        names.extend(others);
+        let mut roster: Vec<&Participant> = names.into_iter().filter_map(|n| self.get(n)).collect();
+        roster.sort_by_key(|p| !members.contains(&p.name));
+        roster
+    }
+}
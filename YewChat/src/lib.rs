@@ -1,16 +1,25 @@
 #![recursion_limit = "512"]
+// `html!` in yew 0.19 expands to code that newer clippy versions flag.
+#![allow(clippy::let_unit_value, clippy::unnecessary_operation)]
 
+mod commands;
 mod components;
+mod directory;
+mod markdown;
+mod media;
+mod mention;
 mod services;
 
 use components::login::Login;
 use components::chat::Chat;
-
+use components::settings::Settings;
+use services::{session, websocket::Handoff};
 
 use wasm_bindgen::prelude::*;
 use yew::functional::*;
 use yew::prelude::*;
 use yew_router::prelude::*;
+use yewchat_protocol::{normalize_room, AuthorId, Channel, DEFAULT_ROOM};
 
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -23,12 +32,18 @@ use std::rc::Rc;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
-#[derive(Debug, Clone, Copy, PartialEq, Routable)]
+#[derive(Debug, Clone, PartialEq, Routable)]
 pub enum Route {
     #[at("/")]
     Login,
     #[at("/chat")]
     Chat,
+    #[at("/chat/dm/:user")]
+    Direct { user: String },
+    #[at("/chat/:id")]
+    Room { id: String },
+    #[at("/settings")]
+    Settings,
     #[not_found]
     #[at("/404")]
     NotFound,
@@ -38,13 +53,40 @@ pub type User = Rc<UserInner>;
 
 #[derive(Debug, PartialEq)]
 pub struct UserInner {
+    /// Empty until the server accepts a nick.
     pub username: RefCell<String>,
+    /// What the server marks our messages with; unlike the nick it survives renames.
+    pub author: RefCell<Option<AuthorId>>,
+    /// Why the chat sent us back to the login screen, shown there once.
+    pub signed_out: RefCell<Option<String>>,
+}
+
+#[derive(Properties, PartialEq)]
+struct RequireNickProps {
+    children: Children,
+}
+
+/// Sends anyone who hasn't picked a nick to the login screen.
+#[function_component(RequireNick)]
+fn require_nick(props: &RequireNickProps) -> Html {
+    let user = use_context::<User>().expect("No context found.");
+    if user.username.borrow().is_empty() {
+        html! {<Redirect<Route> to={Route::Login} />}
+    } else {
+        html! {<>{ for props.children.iter() }</>}
+    }
 }
 
 fn switch(selected_route: &Route) -> Html {
     match selected_route {
         Route::Login => html! {<Login />},
-        Route::Chat => html! {<Chat />},
+        Route::Chat => html! {<RequireNick><Chat channel={Channel::Room(DEFAULT_ROOM.into())} /></RequireNick>},
+        Route::Room { id } => match normalize_room(id) {
+            Some(room) => html! {<RequireNick><Chat channel={Channel::Room(room)} /></RequireNick>},
+            None => html! {<h1>{"404 baby"}</h1>},
+        },
+        Route::Direct { user } => html! {<RequireNick><Chat channel={Channel::Direct(user.clone())} /></RequireNick>},
+        Route::Settings => html! {<Settings />},
         Route::NotFound => html! {<h1>{"404 baby"}</h1>},
     }
 }
@@ -52,18 +94,24 @@ fn switch(selected_route: &Route) -> Html {
 #[function_component(Main)]
 fn main() -> Html {
     let ctx = use_state(|| {
+        // A reload picks up where the tab left off.
         Rc::new(UserInner {
-            username: RefCell::new("initial".into()),
+            username: RefCell::new(session::restore().unwrap_or_default()),
+            author: RefCell::new(None),
+            signed_out: RefCell::new(None),
         })
     });
+    let handoff = use_state(Handoff::default);
 
     html! {
         <ContextProvider<User> context={(*ctx).clone()}>
+        <ContextProvider<Handoff> context={(*handoff).clone()}>
         <BrowserRouter>
             <div class="flex w-screen h-screen">
                 <Switch<Route> render={Switch::render(switch)}/>
             </div>
         </BrowserRouter>
+        </ContextProvider<Handoff>>
         </ContextProvider<User>>
     }
 }
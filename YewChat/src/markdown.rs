@@ -0,0 +1,534 @@
+//! The Markdown subset message text is written in.
+//!
+//! Parsing produces a small tree that is turned into Yew nodes directly; text only
+//! ever ends up in text nodes, so markup inside a message can't become HTML.
+
+use yew::prelude::*;
+
+/// Nesting beyond this (quotes in quotes, bold in italic...) is kept as plain text.
+const MAX_DEPTH: usize = 8;
+/// Schemes a link may point at. Everything else, `javascript:` included, stays text.
+const SAFE_SCHEMES: &[&str] = &["http:", "https:", "mailto:"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block {
+    Paragraph(Vec<Inline>),
+    Code { lang: Option<String>, code: String },
+    List { ordered: bool, items: Vec<Vec<Inline>> },
+    Quote(Vec<Block>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Bold(Vec<Inline>),
+    Italic(Vec<Inline>),
+    Code(String),
+    Link { href: String, children: Vec<Inline> },
+    LineBreak,
+}
+
+pub fn parse(text: &str) -> Vec<Block> {
+    parse_blocks(text, 0)
+}
+
+/// Parses and renders `text` in one go.
+pub fn render(text: &str) -> Html {
+    render_blocks(&parse(text))
+}
+
+fn parse_blocks(text: &str, depth: usize) -> Vec<Block> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut blocks = vec![];
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim_start();
+
+        if trimmed.trim().is_empty() {
+            i += 1;
+        } else if let Some(info) = trimmed.strip_prefix("```") {
+            let lang = Some(info.trim().to_string()).filter(|l| !l.is_empty());
+            let mut code = vec![];
+            i += 1;
+            // An unclosed fence runs to the end of the message.
+            while i < lines.len() && !lines[i].trim_start().starts_with("```") {
+                code.push(lines[i]);
+                i += 1;
+            }
+            i += 1;
+            blocks.push(Block::Code {
+                lang,
+                code: code.join("\n"),
+            });
+        } else if trimmed.starts_with('>') && depth < MAX_DEPTH {
+            let mut quoted = vec![];
+            while i < lines.len() {
+                match lines[i].trim_start().strip_prefix('>') {
+                    Some(rest) => quoted.push(rest.strip_prefix(' ').unwrap_or(rest)),
+                    None => break,
+                }
+                i += 1;
+            }
+            blocks.push(Block::Quote(parse_blocks(&quoted.join("\n"), depth + 1)));
+        } else if let Some((ordered, _)) = list_item(trimmed) {
+            let mut items = vec![];
+            while i < lines.len() {
+                match list_item(lines[i].trim_start()) {
+                    Some((o, item)) if o == ordered => items.push(parse_inline(item, depth)),
+                    _ => break,
+                }
+                i += 1;
+            }
+            blocks.push(Block::List { ordered, items });
+        } else {
+            let start = i;
+            while i < lines.len() && !lines[i].trim().is_empty() && (i == start || !starts_block(lines[i])) {
+                i += 1;
+            }
+            let mut inlines = vec![];
+            for (n, line) in lines[start..i].iter().enumerate() {
+                if n > 0 {
+                    inlines.push(Inline::LineBreak);
+                }
+                inlines.extend(parse_inline(line, depth));
+            }
+            blocks.push(Block::Paragraph(inlines));
+        }
+    }
+    blocks
+}
+
+/// Whether `line` opens something other than a paragraph.
+fn starts_block(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.starts_with("```") || trimmed.starts_with('>') || list_item(trimmed).is_some()
+}
+
+/// `- item`, `* item`, `+ item` or `1. item`: whether it's ordered, and the item text.
+fn list_item(line: &str) -> Option<(bool, &str)> {
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(item) = line.strip_prefix(bullet) {
+            return Some((false, item));
+        }
+    }
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 && digits <= 9 {
+        if let Some(item) = line[digits..].strip_prefix(". ") {
+            return Some((true, item));
+        }
+    }
+    None
+}
+
+fn parse_inline(text: &str, depth: usize) -> Vec<Inline> {
+    let closers = Closers::new(text);
+    let mut out = vec![];
+    let mut plain = String::new();
+    let mut pos = 0;
+
+    while let Some(c) = text[pos..].chars().next() {
+        if let Some((inline, after)) = inline_at(text, pos, depth, &closers) {
+            if !plain.is_empty() {
+                out.push(Inline::Text(std::mem::take(&mut plain)));
+            }
+            out.push(inline);
+            pos = after;
+            continue;
+        }
+        plain.push(c);
+        pos += c.len_utf8();
+        // Emphasis opens only at the start of a word, so `snake_case` stays as is.
+        if c.is_alphanumeric() {
+            let rest = &text[pos..];
+            let word = rest.len() - rest.trim_start_matches(|c: char| c.is_alphanumeric() || c == '_').len();
+            plain.push_str(&rest[..word]);
+            pos += word;
+        }
+    }
+    if !plain.is_empty() {
+        out.push(Inline::Text(plain));
+    }
+    out
+}
+
+/// Byte offsets in one piece of text where each kind of span could end, found in a
+/// single pass. Looking closers up here instead of scanning ahead from every opener
+/// keeps a message full of unmatched `_` or `[` linear to parse.
+struct Closers {
+    backticks: Vec<usize>,
+    /// Starts of `**`, overlapping ones included.
+    double_stars: Vec<usize>,
+    /// Single `*` and `_` that can end an italic, see [`closes_emphasis`].
+    stars: Vec<usize>,
+    underscores: Vec<usize>,
+    link_middles: Vec<usize>,
+    parens: Vec<usize>,
+}
+
+impl Closers {
+    fn new(text: &str) -> Self {
+        let mut closers = Closers {
+            backticks: vec![],
+            double_stars: vec![],
+            stars: vec![],
+            underscores: vec![],
+            link_middles: vec![],
+            parens: vec![],
+        };
+        for (i, c) in text.char_indices() {
+            let after = &text[i + c.len_utf8()..];
+            match c {
+                '`' => closers.backticks.push(i),
+                '*' => {
+                    if after.starts_with('*') {
+                        closers.double_stars.push(i);
+                    }
+                    if closes_emphasis(text, i, '*') {
+                        closers.stars.push(i);
+                    }
+                }
+                '_' if closes_emphasis(text, i, '_') => closers.underscores.push(i),
+                ']' if after.starts_with('(') => closers.link_middles.push(i),
+                ')' => closers.parens.push(i),
+                _ => {}
+            }
+        }
+        closers
+    }
+}
+
+/// The first of `positions` at or after `from`.
+fn next(positions: &[usize], from: usize) -> Option<usize> {
+    positions.get(positions.partition_point(|&p| p < from)).copied()
+}
+
+/// The span starting exactly at byte `pos` of `text`, and where the text after it starts.
+fn inline_at(text: &str, pos: usize, depth: usize, closers: &Closers) -> Option<(Inline, usize)> {
+    let rest = &text[pos..];
+    if rest.starts_with('`') {
+        let end = next(&closers.backticks, pos + 1)?;
+        return Some((Inline::Code(text[pos + 1..end].to_string()), end + 1));
+    }
+    if depth >= MAX_DEPTH {
+        return None;
+    }
+    if rest.starts_with("**") {
+        let body = pos + 2;
+        let mut end = next(&closers.double_stars, body).filter(|&end| end > body)?;
+        // In `**a *b***` the bold closes with the last two stars of the run.
+        while text[end + 2..].starts_with('*') {
+            end += 1;
+        }
+        return Some((Inline::Bold(parse_inline(&text[body..end], depth + 1)), end + 2));
+    }
+    for (marker, ends) in [('*', &closers.stars), ('_', &closers.underscores)] {
+        if let Some(body) = rest.strip_prefix(marker) {
+            let first = body.chars().next().filter(|c| !c.is_whitespace())?;
+            let start = pos + 1;
+            // The closer comes after at least one character of text.
+            let end = next(ends, start + first.len_utf8())?;
+            return Some((Inline::Italic(parse_inline(&text[start..end], depth + 1)), end + 1));
+        }
+    }
+    if rest.starts_with('[') {
+        let label = pos + 1;
+        let middle = next(&closers.link_middles, label)?;
+        let target = middle + 2;
+        let href_end = next(&closers.parens, target)?;
+        let href = safe_url(&text[target..href_end])?;
+        return Some((
+            Inline::Link {
+                href,
+                children: parse_inline(&text[label..middle], depth + 1),
+            },
+            href_end + 1,
+        ));
+    }
+    None
+}
+
+/// Whether the emphasis `marker` at byte `i` of `text` can close an italic: not
+/// after whitespace and not inside a word.
+fn closes_emphasis(text: &str, i: usize, marker: char) -> bool {
+    let (before, after) = (&text[..i], &text[i + 1..]);
+    let inside_word = after.starts_with(|c: char| c.is_alphanumeric());
+    // `**` after an italic opener is bold, not the end of the italic.
+    let doubled = marker == '*' && after.starts_with('*');
+    !before.ends_with(char::is_whitespace) && !inside_word && !doubled
+}
+
+/// `url` if it is an absolute link with an allowed scheme.
+pub fn safe_url(url: &str) -> Option<String> {
+    let url = url.trim();
+    if url.is_empty() || url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return None;
+    }
+    let lower = url.to_ascii_lowercase();
+    SAFE_SCHEMES
+        .iter()
+        .any(|scheme| lower.starts_with(scheme))
+        .then(|| url.to_string())
+}
+
+fn render_blocks(blocks: &[Block]) -> Html {
+    blocks
+        .iter()
+        .map(|block| match block {
+            Block::Paragraph(inlines) => html! { <p class="break-words">{render_inlines(inlines)}</p> },
+            Block::Code { lang, code } => html! {
+                <pre class="p-3 rounded-lg bg-black/40 overflow-x-auto" data-lang={lang.clone()}>
+                    <code class="font-mono text-sm whitespace-pre">{code.clone()}</code>
+                </pre>
+            },
+            Block::List { ordered: true, items } => html! {
+                <ol class="pl-5 list-decimal space-y-1">{render_items(items)}</ol>
+            },
+            Block::List { ordered: false, items } => html! {
+                <ul class="pl-5 list-disc space-y-1">{render_items(items)}</ul>
+            },
+            Block::Quote(blocks) => html! {
+                <blockquote class="pl-3 border-l-2 border-white/30 text-gray-300 space-y-2">{render_blocks(blocks)}</blockquote>
+            },
+        })
+        .collect()
+}
+
+fn render_items(items: &[Vec<Inline>]) -> Html {
+    items
+        .iter()
+        .map(|item| html! { <li>{render_inlines(item)}</li> })
+        .collect()
+}
+
+fn render_inlines(inlines: &[Inline]) -> Html {
+    inlines
+        .iter()
+        .map(|inline| match inline {
+            Inline::Text(text) => html! { {text.clone()} },
+            Inline::Bold(children) => html! { <strong>{render_inlines(children)}</strong> },
+            Inline::Italic(children) => html! { <em>{render_inlines(children)}</em> },
+            Inline::Code(code) => html! { <code class="px-1 rounded bg-black/30 font-mono text-sm">{code.clone()}</code> },
+            Inline::Link { href, children } => html! {
+                <a href={href.clone()} target="_blank" rel="noopener noreferrer nofollow" class="text-purple-300 underline hover:text-purple-200">
+                    {render_inlines(children)}
+                </a>
+            },
+            Inline::LineBreak => html! { <br/> },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Inline {
+        Inline::Text(s.into())
+    }
+
+    fn paragraph(inlines: Vec<Inline>) -> Vec<Block> {
+        vec![Block::Paragraph(inlines)]
+    }
+
+    /// Every link target in `blocks`.
+    fn hrefs(blocks: &[Block]) -> Vec<String> {
+        fn inline(inlines: &[Inline], out: &mut Vec<String>) {
+            for i in inlines {
+                match i {
+                    Inline::Link { href, children } => {
+                        out.push(href.clone());
+                        inline(children, out);
+                    }
+                    Inline::Bold(c) | Inline::Italic(c) => inline(c, out),
+                    _ => {}
+                }
+            }
+        }
+        let mut out = vec![];
+        for b in blocks {
+            match b {
+                Block::Paragraph(i) => inline(i, &mut out),
+                Block::List { items, .. } => items.iter().for_each(|i| inline(i, &mut out)),
+                Block::Quote(q) => out.extend(hrefs(q)),
+                Block::Code { .. } => {}
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(parse("hello there"), paragraph(vec![text("hello there")]));
+        assert_eq!(parse(""), vec![]);
+    }
+
+    #[test]
+    fn emphasis_and_code() {
+        assert_eq!(
+            parse("**bold** and *it* and _it_ and `x < y`"),
+            paragraph(vec![
+                Inline::Bold(vec![text("bold")]),
+                text(" and "),
+                Inline::Italic(vec![text("it")]),
+                text(" and "),
+                Inline::Italic(vec![text("it")]),
+                text(" and "),
+                Inline::Code("x < y".into()),
+            ])
+        );
+    }
+
+    #[test]
+    fn emphasis_needs_word_boundaries() {
+        assert_eq!(parse("snake_case_name"), paragraph(vec![text("snake_case_name")]));
+        assert_eq!(parse("2 * 3 * 4"), paragraph(vec![text("2 * 3 * 4")]));
+        assert_eq!(parse("**"), paragraph(vec![text("**")]));
+        assert_eq!(parse("`unclosed"), paragraph(vec![text("`unclosed")]));
+    }
+
+    #[test]
+    fn nested_emphasis() {
+        assert_eq!(
+            parse("**very *nested***"),
+            paragraph(vec![Inline::Bold(vec![text("very "), Inline::Italic(vec![text("nested")])])])
+        );
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            parse("see [the docs](https://yew.rs/docs)"),
+            paragraph(vec![
+                text("see "),
+                Inline::Link {
+                    href: "https://yew.rs/docs".into(),
+                    children: vec![text("the docs")],
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn fenced_code_keeps_contents_verbatim() {
+        assert_eq!(
+            parse("```rust\nlet x = **1**;\n  <b>hi</b>\n```\nafter"),
+            vec![
+                Block::Code {
+                    lang: Some("rust".into()),
+                    code: "let x = **1**;\n  <b>hi</b>".into(),
+                },
+                Block::Paragraph(vec![text("after")]),
+            ]
+        );
+        assert_eq!(
+            parse("```\nno end"),
+            vec![Block::Code {
+                lang: None,
+                code: "no end".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn lists_and_quotes() {
+        assert_eq!(
+            parse("- one\n- **two**\n1. first\n2. second"),
+            vec![
+                Block::List {
+                    ordered: false,
+                    items: vec![vec![text("one")], vec![Inline::Bold(vec![text("two")])]],
+                },
+                Block::List {
+                    ordered: true,
+                    items: vec![vec![text("first")], vec![text("second")]],
+                },
+            ]
+        );
+        assert_eq!(
+            parse("> quoted\n> > twice\nreply"),
+            vec![
+                Block::Quote(vec![
+                    Block::Paragraph(vec![text("quoted")]),
+                    Block::Quote(paragraph(vec![text("twice")])),
+                ]),
+                Block::Paragraph(vec![text("reply")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn line_breaks_are_kept() {
+        assert_eq!(
+            parse("one\ntwo\n\nthree"),
+            vec![
+                Block::Paragraph(vec![text("one"), Inline::LineBreak, text("two")]),
+                Block::Paragraph(vec![text("three")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn html_stays_text() {
+        let raw = "<script>alert(1)</script><img src=x onerror=alert(1)>";
+        assert_eq!(parse(raw), paragraph(vec![text(raw)]));
+        assert_eq!(
+            parse("**<b onmouseover=alert(1)>**"),
+            paragraph(vec![Inline::Bold(vec![text("<b onmouseover=alert(1)>")])])
+        );
+    }
+
+    #[test]
+    fn unsafe_links_stay_text() {
+        for raw in [
+            "[x](javascript:alert(1))",
+            "[x](JaVaScRiPt:alert(1))",
+            "[x]( javascript:alert(1))",
+            "[x](java\tscript:alert(1))",
+            "[x](data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==)",
+            "[x](vbscript:msgbox(1))",
+            "[x](//evil.example)",
+            "[x](/relative)",
+        ] {
+            let blocks = parse(raw);
+            assert!(hrefs(&blocks).is_empty(), "{} produced a link: {:?}", raw, blocks);
+        }
+    }
+
+    #[test]
+    fn link_targets_keep_quotes_out_of_markup() {
+        // Attributes are set through the DOM, but make sure nothing is lost or split either.
+        let blocks = parse(r#"[x](https://a.example/?q="><script>)"#);
+        assert_eq!(hrefs(&blocks), vec![r#"https://a.example/?q="><script>"#.to_string()]);
+    }
+
+    #[test]
+    fn safe_url_schemes() {
+        assert!(safe_url("https://example.com").is_some());
+        assert!(safe_url("HTTP://example.com").is_some());
+        assert!(safe_url("mailto:a@example.com").is_some());
+        assert!(safe_url("javascript:alert(1)").is_none());
+        assert!(safe_url("https://exa mple.com").is_none());
+        assert!(safe_url("https://example.com/\u{0}").is_none());
+    }
+
+    #[test]
+    fn deep_nesting_is_bounded() {
+        let quotes = ">".repeat(1000) + " deep";
+        assert!(!parse(&quotes).is_empty());
+        let stars = "*a ".repeat(1000);
+        assert!(!parse(&stars).is_empty());
+    }
+
+    #[test]
+    fn unmatched_openers_parse_in_linear_time() {
+        // Each of these used to rescan the rest of the message from every opener.
+        for pattern in ["_a ", "*a ", "[", "**a ", "`", "[a](", "[a](javascript:x) "] {
+            let raw = pattern.repeat(100_000 / pattern.len());
+            let started = std::time::Instant::now();
+            assert!(!parse(&raw).is_empty());
+            let took = started.elapsed();
+            assert!(took.as_secs() < 2, "{:?} x {} took {:?}", pattern, raw.len() / pattern.len(), took);
+        }
+    }
+}
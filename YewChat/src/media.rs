@@ -0,0 +1,204 @@
+//! Finds links to images, video and audio in message text so they can be previewed.
+
+use crate::markdown::safe_url;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MediaKind {
+    Image,
+    Video,
+    Audio,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Media {
+    pub url: String,
+    pub kind: MediaKind,
+}
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "svg", "avif", "bmp"];
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "webm", "ogv", "mov", "m4v"];
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "ogg", "oga", "wav", "m4a", "flac", "opus", "aac"];
+/// Query parameters image hosts and CDNs use to say what they serve, e.g. `?format=png`
+/// or `?mime=image/png`.
+const TYPE_HINT_PARAMS: &[&str] = &["format", "fm", "ext", "type", "mime", "mimetype", "content_type", "content-type"];
+/// Characters that commonly end a sentence right after a URL and are rarely part of one.
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', '\'', '"'];
+
+/// Every previewable link in `text`, in order of appearance, without repeats.
+pub fn media(text: &str) -> Vec<Media> {
+    let mut found: Vec<Media> = vec![];
+    for url in urls(text) {
+        if let Some(kind) = classify(url) {
+            if !found.iter().any(|m| m.url == url) {
+                found.push(Media {
+                    url: url.to_string(),
+                    kind,
+                });
+            }
+        }
+    }
+    found
+}
+
+/// The `http(s)` URLs in `text`, including ones inside Markdown links.
+pub fn urls(text: &str) -> Vec<&str> {
+    let mut out = vec![];
+    let mut rest = text;
+    while let Some(start) = find_scheme(rest) {
+        let candidate = &rest[start..];
+        let end = candidate
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '`' | '[' | ']'))
+            .unwrap_or(candidate.len());
+        let url = trim_url(&candidate[..end]);
+        if safe_url(url).is_some() && url.len() > "https://".len() {
+            out.push(url);
+        }
+        rest = &candidate[end.max(1)..];
+    }
+    out
+}
+
+/// What `url` points at, judged by its file extension or a type hint in the query.
+pub fn classify(url: &str) -> Option<MediaKind> {
+    let without_fragment = url.split('#').next().unwrap_or_default();
+    let (path, query) = match without_fragment.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (without_fragment, None),
+    };
+    let file = path.rsplit('/').next().unwrap_or_default();
+    let from_extension = file.rsplit_once('.').and_then(|(_, ext)| by_extension(ext));
+
+    from_extension.or_else(|| {
+        query?.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            if !TYPE_HINT_PARAMS.contains(&key.to_ascii_lowercase().as_str()) {
+                return None;
+            }
+            let value = value.replace("%2F", "/").replace("%2f", "/");
+            if value.contains('/') {
+                from_mime(&value)
+            } else {
+                by_extension(&value)
+            }
+        })
+    })
+}
+
+/// What a MIME type like `image/png` describes, if it is something we can preview.
+pub fn from_mime(mime: &str) -> Option<MediaKind> {
+    match mime.split_once('/')?.0.trim().to_ascii_lowercase().as_str() {
+        "image" => Some(MediaKind::Image),
+        "video" => Some(MediaKind::Video),
+        "audio" => Some(MediaKind::Audio),
+        _ => None,
+    }
+}
+
+fn by_extension(ext: &str) -> Option<MediaKind> {
+    let ext = ext.to_ascii_lowercase();
+    if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+        Some(MediaKind::Image)
+    } else if VIDEO_EXTENSIONS.contains(&ext.as_str()) {
+        Some(MediaKind::Video)
+    } else if AUDIO_EXTENSIONS.contains(&ext.as_str()) {
+        Some(MediaKind::Audio)
+    } else {
+        None
+    }
+}
+
+fn find_scheme(text: &str) -> Option<usize> {
+    let lower = text.to_ascii_lowercase();
+    match (lower.find("http://"), lower.find("https://")) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Drops sentence punctuation after a URL, and a closing paren it didn't open
+/// (as in `(see https://x.dev/a.png)` or a Markdown link target).
+fn trim_url(url: &str) -> &str {
+    let mut url = url;
+    loop {
+        let trimmed = url.trim_end_matches(TRAILING_PUNCTUATION);
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(inner) if trimmed.matches('(').count() < trimmed.matches(')').count() => inner,
+            _ => trimmed,
+        };
+        if trimmed.len() == url.len() {
+            return url;
+        }
+        url = trimmed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_by_extension() {
+        assert_eq!(classify("https://x.dev/cat.GIF"), Some(MediaKind::Image));
+        assert_eq!(classify("https://x.dev/a/b.webp"), Some(MediaKind::Image));
+        assert_eq!(classify("https://x.dev/logo.svg"), Some(MediaKind::Image));
+        assert_eq!(classify("https://x.dev/clip.mp4"), Some(MediaKind::Video));
+        assert_eq!(classify("https://x.dev/song.mp3"), Some(MediaKind::Audio));
+        assert_eq!(classify("https://x.dev/page.html"), None);
+        assert_eq!(classify("https://x.dev/"), None);
+    }
+
+    #[test]
+    fn ignores_query_and_fragment_when_reading_the_extension() {
+        assert_eq!(classify("https://x.dev/cat.png?size=large#top"), Some(MediaKind::Image));
+        assert_eq!(classify("https://x.dev/page?file=cat.png"), None);
+        assert_eq!(classify("https://x.dev/cat.png.html"), None);
+    }
+
+    #[test]
+    fn uses_type_hints() {
+        assert_eq!(classify("https://cdn.x.dev/abc?fm=jpg&w=400"), Some(MediaKind::Image));
+        assert_eq!(classify("https://cdn.x.dev/abc?mime=video%2Fmp4"), Some(MediaKind::Video));
+        assert_eq!(classify("https://cdn.x.dev/abc?type=audio/ogg"), Some(MediaKind::Audio));
+        assert_eq!(classify("https://cdn.x.dev/abc?type=text/html"), None);
+        assert_eq!(from_mime("Image/PNG"), Some(MediaKind::Image));
+        assert_eq!(from_mime("application/pdf"), None);
+        assert_eq!(from_mime(""), None);
+    }
+
+    #[test]
+    fn extracts_urls_from_text() {
+        assert_eq!(
+            urls("look: https://x.dev/a.png, and (https://x.dev/b.gif) or [c](https://x.dev/c.jpg)."),
+            vec!["https://x.dev/a.png", "https://x.dev/b.gif", "https://x.dev/c.jpg"]
+        );
+        assert_eq!(
+            urls("https://en.wikipedia.org/wiki/Rust_(programming_language)"),
+            vec!["https://en.wikipedia.org/wiki/Rust_(programming_language)"]
+        );
+        assert!(urls("ftp://x.dev/a.png javascript:alert(1).png https://").is_empty());
+    }
+
+    #[test]
+    fn text_that_merely_ends_in_gif_is_not_media() {
+        assert!(media("I love .gif").is_empty());
+        assert!(media("my favourite format is gif").is_empty());
+    }
+
+    #[test]
+    fn collects_each_link_once() {
+        let found = media("https://x.dev/a.png https://x.dev/a.png https://x.dev/b.mp4 https://x.dev/doc");
+        assert_eq!(
+            found,
+            vec![
+                Media {
+                    url: "https://x.dev/a.png".into(),
+                    kind: MediaKind::Image,
+                },
+                Media {
+                    url: "https://x.dev/b.mp4".into(),
+                    kind: MediaKind::Video,
+                },
+            ]
+        );
+    }
+}
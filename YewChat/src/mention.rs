@@ -0,0 +1,113 @@
+//! `@name` mentions: finding them in a message and completing them while typing.
+
+/// Punctuation allowed right after a mention, as in "thanks @bob!".
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', ')', '\'', '"'];
+
+/// The distinct `known` names that `text` mentions as `@name`, in order of appearance.
+pub fn mentioned(text: &str, known: &[String]) -> Vec<String> {
+    let mut found: Vec<String> = vec![];
+    for word in text.split_whitespace() {
+        let Some(name) = word.strip_prefix('@') else { continue };
+        // A nick may itself end in punctuation, so try the word as written first.
+        let name = [name, name.trim_end_matches(TRAILING_PUNCTUATION)]
+            .into_iter()
+            .find(|n| known.iter().any(|k| k == n));
+        if let Some(name) = name {
+            if !found.iter().any(|f| f == name) {
+                found.push(name.to_string());
+            }
+        }
+    }
+    found
+}
+
+/// The mention being typed with the caret at `caret` (in UTF-16 code units, as the
+/// DOM reports it): the byte offset of its `@` and the part of the name typed so far.
+pub fn query(text: &str, caret: u32) -> Option<(usize, &str)> {
+    let before = &text[..byte_offset(text, caret)];
+    let start = before
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let prefix = before[start..].strip_prefix('@')?;
+    (!prefix.contains('@')).then_some((start, prefix))
+}
+
+/// `text` with the mention starting at byte `at` replaced by `@name `, and the caret
+/// position (in UTF-16 code units) just after it.
+pub fn complete(text: &str, at: usize, name: &str) -> (String, u32) {
+    let end = text[at..].find(char::is_whitespace).map_or(text.len(), |i| at + i);
+    let head = format!("{}@{} ", &text[..at], name);
+    let caret = head.encode_utf16().count() as u32;
+    (format!("{}{}", head, text[end..].trim_start()), caret)
+}
+
+/// Up to `limit` of `names` starting with `prefix`, ignoring case, in the order given.
+pub fn suggestions<'a>(prefix: &str, names: impl IntoIterator<Item = &'a str>, limit: usize) -> Vec<String> {
+    let prefix = prefix.to_lowercase();
+    let mut out: Vec<String> = vec![];
+    for name in names {
+        if out.len() == limit {
+            break;
+        }
+        if name.to_lowercase().starts_with(&prefix) && !out.iter().any(|o| o == name) {
+            out.push(name.to_string());
+        }
+    }
+    out
+}
+
+fn byte_offset(text: &str, utf16: u32) -> usize {
+    let mut units = 0;
+    for (i, c) in text.char_indices() {
+        if units >= utf16 as usize {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn finds_known_mentions() {
+        let known = names(&["alice", "bob", "c.j."]);
+        assert_eq!(mentioned("hi @bob, @alice and @bob!", &known), names(&["bob", "alice"]));
+        assert_eq!(mentioned("ping @c.j.", &known), names(&["c.j."]));
+        assert!(mentioned("mail me at bob@example.com or @carol", &known).is_empty());
+    }
+
+    #[test]
+    fn finds_the_mention_being_typed() {
+        assert_eq!(query("hey @al", 7), Some((4, "al")));
+        assert_eq!(query("hey @", 5), Some((4, "")));
+        assert_eq!(query("hey @al there", 7), Some((4, "al")));
+        assert_eq!(query("hey @al there", 13), None);
+        assert_eq!(query("bob@ex", 6), None);
+        // The emoji is two UTF-16 units but four bytes.
+        assert_eq!(query("🎉 @b", 5), Some((5, "b")));
+    }
+
+    #[test]
+    fn completes_the_mention() {
+        assert_eq!(complete("hey @al", 4, "alice"), ("hey @alice ".to_string(), 11));
+        assert_eq!(complete("@al how are you", 0, "alice"), ("@alice how are you".to_string(), 7));
+        assert_eq!(complete("🎉 @b", 5, "bob"), ("🎉 @bob ".to_string(), 8));
+    }
+
+    #[test]
+    fn suggests_by_prefix() {
+        let all = ["Alice", "albert", "bob", "alice2", "Alice"];
+        assert_eq!(suggestions("al", all, 2), names(&["Alice", "albert"]));
+        assert_eq!(suggestions("", all, 10), names(&["Alice", "albert", "bob", "alice2"]));
+        assert!(suggestions("z", all, 10).is_empty());
+    }
+}
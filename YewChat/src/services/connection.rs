@@ -0,0 +1,62 @@
+use std::collections::HashSet;
+use yew_agent::{Agent, AgentLink, Context, HandlerId};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConnectionState {
+    Connecting,
+    Open,
+    Reconnecting(u32),
+    Closed(String),
+}
+
+impl ConnectionState {
+    pub fn is_open(&self) -> bool {
+        matches!(self, ConnectionState::Open)
+    }
+}
+
+/// Broadcasts the websocket's connection state to every subscriber.
+///
+/// The last state is kept and replayed to new subscribers so a component that
+/// mounts after the socket opened doesn't sit on "Connecting" forever.
+pub struct ConnectionBus {
+    link: AgentLink<ConnectionBus>,
+    subscribers: HashSet<HandlerId>,
+    state: ConnectionState,
+}
+
+impl Agent for ConnectionBus {
+    type Reach = Context<Self>;
+    type Message = ();
+    type Input = ConnectionState;
+    type Output = ConnectionState;
+
+    fn create(link: AgentLink<Self>) -> Self {
+        Self {
+            link,
+            subscribers: HashSet::new(),
+            state: ConnectionState::Connecting,
+        }
+    }
+
+    fn update(&mut self, _msg: Self::Message) {}
+
+    fn handle_input(&mut self, state: Self::Input, _id: HandlerId) {
+        self.state = state;
+        for sub in self.subscribers.iter() {
+            self.link.respond(*sub, self.state.clone())
+        }
+    }
+
+    fn connected(&mut self, id: HandlerId) {
+        // Dispatchers (the websocket service) have nothing to respond to.
+        if id.is_respondable() {
+            self.subscribers.insert(id);
+            self.link.respond(id, self.state.clone());
+        }
+    }
+
+    fn disconnected(&mut self, id: HandlerId) {
+        self.subscribers.remove(&id);
+    }
+}
@@ -0,0 +1,132 @@
+use gloo_storage::{LocalStorage, Storage};
+use gloo_utils::{document, window};
+use web_sys::UrlSearchParams;
+use yewchat_protocol::Attachment;
+
+/// localStorage key holding the address picked on the settings screen.
+const OVERRIDE_KEY: &str = "yewchat.server";
+/// `<meta name="yewchat-server" content="ws://...">` in `static/index.html`.
+const META_NAME: &str = "yewchat-server";
+/// `?server=wss://...` on the page URL.
+const QUERY_PARAM: &str = "server";
+/// Port the bundled websocket server listens on by default.
+const DEFAULT_PORT: u16 = 8080;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Query,
+    Settings,
+    Meta,
+    Location,
+}
+
+impl Source {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Source::Query => "page URL (?server=)",
+            Source::Settings => "settings override",
+            Source::Meta => "index.html meta tag",
+            Source::Location => "derived from the page address",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Endpoint {
+    pub url: String,
+    pub source: Source,
+}
+
+/// Picks the server address, first match wins: query parameter, settings override,
+/// meta tag, and finally the page's own host on the default port.
+pub fn resolve() -> Endpoint {
+    let candidates = [
+        (Source::Query, query_param()),
+        (Source::Settings, stored_override()),
+        (Source::Meta, meta_tag()),
+    ];
+    for (source, url) in candidates {
+        match url.map(|u| validate(&u)) {
+            Some(Ok(url)) => return Endpoint { url, source },
+            Some(Err(e)) => log::warn!("Ignoring server address from {}: {}", source.describe(), e),
+            None => {}
+        }
+    }
+    Endpoint {
+        url: from_location(),
+        source: Source::Location,
+    }
+}
+
+pub fn stored_override() -> Option<String> {
+    LocalStorage::get(OVERRIDE_KEY).ok()
+}
+
+pub fn set_override(url: &str) -> Result<String, String> {
+    let url = validate(url)?;
+    LocalStorage::set(OVERRIDE_KEY, &url).map_err(|e| e.to_string())?;
+    Ok(url)
+}
+
+pub fn clear_override() {
+    LocalStorage::delete(OVERRIDE_KEY);
+}
+
+/// Where the server serves files: over HTTP on the same host and port as the
+/// websocket. Resolved once by the caller rather than for every attachment.
+pub fn files_base() -> String {
+    let url = resolve().url;
+    let (scheme, rest) = match url.strip_prefix("wss://") {
+        Some(rest) => ("https", rest),
+        None => ("http", url.strip_prefix("ws://").unwrap_or(&url)),
+    };
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    format!("{}://{}", scheme, host)
+}
+
+/// Where to download `attachment`, given [`files_base`].
+pub fn file_url(base: &str, attachment: &Attachment) -> String {
+    format!("{}{}", base, attachment.path())
+}
+
+/// Trims `url` and checks it is something `WebSocket::open` can take.
+pub fn validate(url: &str) -> Result<String, String> {
+    let url = url.trim();
+    let rest = url
+        .strip_prefix("ws://")
+        .or_else(|| url.strip_prefix("wss://"))
+        .ok_or_else(|| "address must start with ws:// or wss://".to_string())?;
+    if rest.is_empty() || rest.starts_with('/') {
+        return Err("address is missing a host".into());
+    }
+    Ok(url.to_string())
+}
+
+fn query_param() -> Option<String> {
+    let search = window().location().search().ok()?;
+    UrlSearchParams::new_with_str(&search).ok()?.get(QUERY_PARAM)
+}
+
+fn meta_tag() -> Option<String> {
+    let selector = format!("meta[name=\"{}\"]", META_NAME);
+    document()
+        .query_selector(&selector)
+        .ok()??
+        .get_attribute("content")
+        .filter(|c| !c.trim().is_empty())
+}
+
+/// `wss://` when the page itself is served over https, so browsers don't block mixed content.
+fn from_location() -> String {
+    let location = window().location();
+    let scheme = match location.protocol().as_deref() {
+        Ok("https:") => "wss",
+        _ => "ws",
+    };
+    let host = location
+        .hostname()
+        .ok()
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "127.0.0.1".into());
+    format!("{}://{}:{}", scheme, host, DEFAULT_PORT)
+}
@@ -0,0 +1,214 @@
+use std::collections::HashSet;
+
+use js_sys::{Array, Date, Object, Promise, Reflect};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbKeyRange, IdbObjectStore, IdbRequest, IdbTransactionMode};
+use yewchat_protocol::{Channel, ChatMessage};
+
+const DB_NAME: &str = "yewchat";
+const DB_VERSION: u32 = 2;
+const STORE: &str = "messages";
+/// Index on the owner-and-channel key, see [`conversation_key`].
+const BY_CONVERSATION: &str = "conversation";
+/// Index on the message time, used to expire old entries.
+const BY_TIME: &str = "time";
+
+/// Messages kept per conversation; older ones are dropped first.
+pub const MAX_PER_CONVERSATION: u32 = 500;
+/// Messages older than this are dropped whatever the count.
+pub const MAX_AGE_MS: f64 = 30.0 * 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Everything stored for `channel` as seen by `owner`, oldest first.
+pub async fn load(owner: &str, channel: &Channel) -> Result<Vec<ChatMessage>, String> {
+    let db = open().await?;
+    let store = store(&db, IdbTransactionMode::Readonly)?;
+    let index = store.index(BY_CONVERSATION).map_err(js_err)?;
+    let key = JsValue::from_str(&conversation_key(owner, channel));
+    let records: Array = request(index.get_all_with_key(&key).map_err(js_err)?).await?.unchecked_into();
+
+    let mut messages = Vec::with_capacity(records.length() as usize);
+    for record in records.iter() {
+        let json = Reflect::get(&record, &"json".into()).map_err(js_err)?;
+        match json.as_string().map(|json| serde_json::from_str::<ChatMessage>(&json)) {
+            Some(Ok(message)) => messages.push(message),
+            // Written by an older build with a different message shape; skip it.
+            _ => log::warn!("Skipping unreadable history entry in {}", STORE),
+        }
+    }
+    messages.sort_by_key(|m| m.id);
+    Ok(messages)
+}
+
+/// Stores `messages` under `channel` for `owner`, then trims that conversation.
+///
+/// Entries are keyed by message id, so storing a message twice keeps one copy.
+pub async fn append(owner: &str, channel: &Channel, messages: &[ChatMessage]) -> Result<(), String> {
+    let conversation = conversation_key(owner, channel);
+    let db = open().await?;
+    let store = store(&db, IdbTransactionMode::Readwrite)?;
+    for message in messages {
+        let json = serde_json::to_string(message).map_err(|e| e.to_string())?;
+        let record = Object::new();
+        for (field, value) in [
+            ("conversation", JsValue::from_str(&conversation)),
+            ("time", JsValue::from_f64(message.time.millis() as f64)),
+            ("json", JsValue::from_str(&json)),
+        ] {
+            Reflect::set(&record, &field.into(), &value).map_err(js_err)?;
+        }
+        let key = Array::of2(&JsValue::from_str(&conversation), &JsValue::from_f64(message.id.0 as f64));
+        request(store.put_with_key(&record, &key).map_err(js_err)?).await?;
+    }
+
+    // Oldest entries of this conversation go first once it is over the cap.
+    let index = store.index(BY_CONVERSATION).map_err(js_err)?;
+    let keys: Array = request(index.get_all_keys_with_key(&conversation.into()).map_err(js_err)?)
+        .await?
+        .unchecked_into();
+    let excess = keys.length().saturating_sub(MAX_PER_CONVERSATION);
+    for key in keys.iter().take(excess as usize) {
+        request(store.delete(&key).map_err(js_err)?).await?;
+    }
+    Ok(())
+}
+
+/// Drops every entry older than [`MAX_AGE_MS`], across all users and conversations.
+pub async fn expire() -> Result<(), String> {
+    let db = open().await?;
+    let store = store(&db, IdbTransactionMode::Readwrite)?;
+    let index = store.index(BY_TIME).map_err(js_err)?;
+    let range = IdbKeyRange::upper_bound(&JsValue::from_f64(Date::now() - MAX_AGE_MS)).map_err(js_err)?;
+    let keys: Array = request(index.get_all_keys_with_key(&range).map_err(js_err)?)
+        .await?
+        .unchecked_into();
+    for key in keys.iter() {
+        request(store.delete(&key).map_err(js_err)?).await?;
+    }
+    Ok(())
+}
+
+/// Deletes all locally stored history.
+pub async fn clear() -> Result<(), String> {
+    let db = open().await?;
+    let store = store(&db, IdbTransactionMode::Readwrite)?;
+    request(store.clear().map_err(js_err)?).await?;
+    Ok(())
+}
+
+/// Entries are keyed by owner too, so two nicks sharing a browser keep separate DMs.
+fn conversation_key(owner: &str, channel: &Channel) -> String {
+    match channel {
+        Channel::Room(room) => format!("{}/room/{}", owner, room),
+        Channel::Direct(user) => format!("{}/direct/{}", owner, user),
+    }
+}
+
+async fn open() -> Result<IdbDatabase, String> {
+    let factory = gloo_utils::window()
+        .indexed_db()
+        .map_err(js_err)?
+        .ok_or_else(|| "IndexedDB is not available".to_string())?;
+    let open = factory.open_with_u32(DB_NAME, DB_VERSION).map_err(js_err)?;
+
+    let upgrade = open.clone();
+    let onupgradeneeded = Closure::once_into_js(move || {
+        let created = upgrade
+            .result()
+            .map(|db| db.unchecked_into::<IdbDatabase>())
+            .and_then(|db| {
+                if !db.object_store_names().contains(STORE) {
+                    return create_store(&db).map(drop);
+                }
+                // Version 1 kept entries under auto-increment keys; carry them over
+                // to the keyed store within this same upgrade.
+                let legacy = upgrade
+                    .transaction()
+                    .ok_or_else(|| JsValue::from_str("upgrade has no transaction"))?
+                    .object_store(STORE)?;
+                let read = legacy.get_all()?;
+                let done = read.clone();
+                let onsuccess = Closure::once_into_js(move || {
+                    let migrated = done.result().and_then(|records| {
+                        db.delete_object_store(STORE)?;
+                        migrate_v1(&create_store(&db)?, &records.unchecked_into())
+                    });
+                    if let Err(e) = migrated {
+                        log::error!("Could not migrate the history store: {}", js_err(e));
+                    }
+                });
+                read.set_onsuccess(Some(onsuccess.unchecked_ref()));
+                Ok(())
+            });
+        if let Err(e) = created {
+            log::error!("Could not create the history store: {}", js_err(e));
+        }
+    });
+    open.set_onupgradeneeded(Some(onupgradeneeded.unchecked_ref()));
+
+    Ok(request(open.unchecked_into()).await?.unchecked_into())
+}
+
+fn create_store(db: &IdbDatabase) -> Result<IdbObjectStore, JsValue> {
+    let store = db.create_object_store(STORE)?;
+    store.create_index_with_str(BY_CONVERSATION, "conversation")?;
+    store.create_index_with_str(BY_TIME, "time")?;
+    Ok(store)
+}
+
+/// Re-keys version 1 `records`, which predate message ids, by their time instead.
+/// The server numbers messages up from its start time in milliseconds, so a time
+/// sorts where the message's id would have and is unlikely to meet a real one.
+fn migrate_v1(store: &IdbObjectStore, records: &Array) -> Result<(), JsValue> {
+    let mut taken = HashSet::new();
+    for record in records.iter() {
+        let conversation = Reflect::get(&record, &"conversation".into())?.as_string().unwrap_or_default();
+        let time = Reflect::get(&record, &"time".into())?.as_f64().unwrap_or_default() as u64;
+        let json = Reflect::get(&record, &"json".into())?.as_string().unwrap_or_default();
+        let Ok(serde_json::Value::Object(mut message)) = serde_json::from_str(&json) else {
+            continue;
+        };
+        // Two messages in the same millisecond still need distinct keys.
+        let mut id = time;
+        while !taken.insert((conversation.clone(), id)) {
+            id += 1;
+        }
+        message.entry("id").or_insert(id.into());
+        let json = serde_json::Value::Object(message).to_string();
+        Reflect::set(&record, &"json".into(), &JsValue::from_str(&json))?;
+        let key = Array::of2(&JsValue::from_str(&conversation), &JsValue::from_f64(id as f64));
+        store.put_with_key(&record, &key)?;
+    }
+    Ok(())
+}
+
+fn store(db: &IdbDatabase, mode: IdbTransactionMode) -> Result<IdbObjectStore, String> {
+    db.transaction_with_str_and_mode(STORE, mode)
+        .and_then(|tx| tx.object_store(STORE))
+        .map_err(js_err)
+}
+
+/// Waits for `req` to succeed or fail.
+async fn request(req: IdbRequest) -> Result<JsValue, String> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        let done = req.clone();
+        let onsuccess = Closure::once_into_js(move || {
+            let _ = resolve.call1(&JsValue::NULL, &done.result().unwrap_or(JsValue::UNDEFINED));
+        });
+        let failed = req.clone();
+        let onerror = Closure::once_into_js(move || {
+            let error = failed.error().ok().flatten().map(JsValue::from).unwrap_or(JsValue::UNDEFINED);
+            let _ = reject.call1(&JsValue::NULL, &error);
+        });
+        req.set_onsuccess(Some(onsuccess.unchecked_ref()));
+        req.set_onerror(Some(onerror.unchecked_ref()));
+    });
+    JsFuture::from(promise).await.map_err(js_err)
+}
+
+fn js_err(e: JsValue) -> String {
+    e.dyn_ref::<js_sys::Error>()
+        .map(|e| String::from(e.message()))
+        .or_else(|| e.as_string())
+        .unwrap_or_else(|| format!("{:?}", e))
+}
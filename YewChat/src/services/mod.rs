@@ -1,2 +1,6 @@
 pub mod websocket;
-pub mod event_bus;
\ No newline at end of file
+pub mod event_bus;
+pub mod connection;
+pub mod endpoint;
+pub mod history;
+pub mod session;
\ No newline at end of file
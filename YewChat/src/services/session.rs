@@ -0,0 +1,46 @@
+use gloo_storage::{SessionStorage, Storage};
+use yewchat_protocol::{validate_nick, SessionToken};
+
+/// sessionStorage key holding the nick the server last accepted. Per tab, so a
+/// second tab logs in separately instead of fighting the first for the nick.
+const NICK_KEY: &str = "yewchat.nick";
+/// sessionStorage key holding this tab's [`SessionToken`].
+const TOKEN_KEY: &str = "yewchat.session";
+
+/// The nick to reconnect as after a reload, if it still passes the rules.
+pub fn restore() -> Option<String> {
+    let nick: String = SessionStorage::get(NICK_KEY).ok()?;
+    validate_nick(&nick).ok()
+}
+
+pub fn save(nick: &str) {
+    if let Err(e) = SessionStorage::set(NICK_KEY, nick) {
+        log::warn!("Could not remember the session: {}", e);
+    }
+}
+
+/// The token this tab registers with, made up on first use. It is what lets the
+/// server hand our messages back to us after a reconnect or a rename, so it has
+/// to be unguessable by whoever takes our nick next.
+pub fn token() -> SessionToken {
+    if let Ok(token) = SessionStorage::get::<String>(TOKEN_KEY) {
+        return SessionToken(token);
+    }
+    let mut bytes = [0u8; 16];
+    let crypto = web_sys::window().and_then(|w| w.crypto().ok());
+    match crypto.map(|c| c.get_random_values_with_u8_array(&mut bytes)) {
+        Some(Ok(_)) => {}
+        _ => bytes.iter_mut().for_each(|b| *b = (js_sys::Math::random() * 256.0) as u8),
+    }
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    if let Err(e) = SessionStorage::set(TOKEN_KEY, &token) {
+        log::warn!("Could not remember the session: {}", e);
+    }
+    SessionToken(token)
+}
+
+/// Forgets the nick. The token stays for the life of the tab, so signing back in
+/// under any nick still owns what was sent before.
+pub fn clear() {
+    SessionStorage::delete(NICK_KEY);
+}
@@ -1,50 +1,270 @@
-use futures::{channel::mpsc::Sender, SinkExt, StreamExt};
-use reqwasm::websocket::{futures::WebSocket, Message};
-use yew_agent::Dispatched;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use futures::{
+    channel::{
+        mpsc::{Receiver, Sender},
+        oneshot,
+    },
+    future::{select, Either},
+    SinkExt, StreamExt,
+};
+use gloo_timers::future::TimeoutFuture;
+use reqwasm::websocket::{futures::WebSocket, Message, State};
+use yew_agent::{Dispatched, Dispatcher};
+use yewchat_protocol::{Chunk, ClientFrame};
+use crate::services::endpoint;
+use crate::services::connection::{ConnectionBus, ConnectionState};
 use crate::services::event_bus::{EventBus, Request};
 
 use wasm_bindgen_futures::spawn_local;
 
+/// First retry delay; doubled on every consecutive failure.
+const BACKOFF_BASE_MS: u32 = 500;
+/// Upper bound for a single retry delay.
+const BACKOFF_MAX_MS: u32 = 30_000;
+
 pub struct WebsocketService {
-    pub tx: Sender<String>,
+    pub tx: Sender<Message>,
+    /// The register frame sent on every (re)connect, shared with the supervisor.
+    register: Rc<RefCell<String>>,
+    /// Never sent on; dropping it tells a supervisor waiting out a backoff to stop.
+    _alive: oneshot::Sender<()>,
+}
+
+/// A connection registered on the login screen, waiting for the chat to take it
+/// over so the accepted nick isn't given up in between.
+#[derive(Clone, Default)]
+pub struct Handoff(Rc<RefCell<Option<WebsocketService>>>);
+
+impl PartialEq for Handoff {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Handoff {
+    pub fn put(&self, wss: WebsocketService) {
+        *self.0.borrow_mut() = Some(wss);
+    }
+
+    pub fn take(&self) -> Option<WebsocketService> {
+        self.0.borrow_mut().take()
+    }
+}
+
+/// Why a single connection ended.
+enum Disconnect {
+    /// The socket closed or errored; try again after a backoff.
+    Lost,
+    /// The socket can't be opened at all, retrying won't help.
+    Failed(String),
+    /// Every `Sender` was dropped, nobody is left to talk to.
+    Shutdown,
 }
 
 impl WebsocketService {
-    pub fn new() -> Self {
-        let ws = WebSocket::open("ws://127.0.0.1:8080").unwrap();
-        let (mut write, mut read) = ws.split();
-        let (in_tx, mut in_rx) = futures::channel::mpsc::channel::<String>(1000);
-        let mut event_bus = EventBus::dispatcher();
-
-        spawn_local(async move {
-            while let Some(s) = in_rx.next().await {
-                log::debug!("Got event from channel! {}", s);
-                write.send(Message::Text(s)).await.unwrap();
+    /// Opens the connection and keeps it alive for as long as `tx` has senders.
+    ///
+    /// `register` is sent first on every (re)connect so the server knows who we are
+    /// again after a drop; frames queued on `tx` while offline are flushed afterwards.
+    pub fn new(register: ClientFrame) -> Self {
+        let (in_tx, in_rx) = futures::channel::mpsc::channel::<Message>(1000);
+        let register = Rc::new(RefCell::new(register.to_json()));
+        let (alive, closed) = oneshot::channel();
+
+        let supervisor = Supervisor {
+            register: register.clone(),
+            in_rx,
+            closed,
+            pending: None,
+            attempt: 0,
+            event_bus: EventBus::dispatcher(),
+            status: ConnectionBus::dispatcher(),
+        };
+        spawn_local(supervisor.run());
+
+        Self {
+            tx: in_tx,
+            register,
+            _alive: alive,
+        }
+    }
+
+    /// Registers with `register` from the next reconnect on, once the server has
+    /// accepted it on this connection.
+    pub fn set_register(&self, register: &ClientFrame) {
+        *self.register.borrow_mut() = register.to_json();
+    }
+
+    pub fn send(&self, frame: &ClientFrame) {
+        if let Err(e) = self.try_send(frame) {
+            log::debug!("Error sending to channel: {}", e);
+        }
+    }
+
+    /// Like [`send`](Self::send), but says when the frame couldn't even be queued,
+    /// because the queue is full or the connection was given up on.
+    pub fn try_send(&self, frame: &ClientFrame) -> Result<(), String> {
+        self.queue(Message::Text(frame.to_json()))
+    }
+
+    /// Queues one binary piece of a file announced with `ClientFrame::Upload`.
+    pub fn send_chunk(&self, chunk: &Chunk) {
+        if let Err(e) = self.queue(Message::Bytes(chunk.encode())) {
+            log::debug!("Error sending to channel: {}", e);
+        }
+    }
+
+    fn queue(&self, message: Message) -> Result<(), String> {
+        self.tx.clone().try_send(message).map_err(|e| {
+            if e.is_full() {
+                "Too many messages waiting to be sent".to_string()
+            } else {
+                "The connection was closed".to_string()
+            }
+        })
+    }
+}
+
+/// Owns the outgoing queue across reconnects and reports every state change.
+struct Supervisor {
+    register: Rc<RefCell<String>>,
+    in_rx: Receiver<Message>,
+    /// Cancelled once the service is dropped, even with frames still queued on `in_rx`.
+    closed: oneshot::Receiver<()>,
+    /// A frame taken off `in_rx` that didn't make it out before the socket dropped.
+    pending: Option<Message>,
+    attempt: u32,
+    event_bus: Dispatcher<EventBus>,
+    status: Dispatcher<ConnectionBus>,
+}
+
+impl Supervisor {
+    async fn run(mut self) {
+        loop {
+            if self.attempt == 0 {
+                self.status.send(ConnectionState::Connecting);
+            }
+
+            // Connecting would register a nick nobody is using any more.
+            let outcome = if matches!(self.closed.try_recv(), Err(oneshot::Canceled)) {
+                Disconnect::Shutdown
+            } else {
+                self.connect().await
+            };
+            match outcome {
+                Disconnect::Lost => {
+                    let delay = backoff_delay(self.attempt);
+                    self.attempt = self.attempt.saturating_add(1);
+                    self.status.send(ConnectionState::Reconnecting(self.attempt));
+                    log::debug!("WebSocket lost, reconnecting in {}ms (attempt {})", delay, self.attempt);
+                    if let Either::Right(_) = select(TimeoutFuture::new(delay), &mut self.closed).await {
+                        self.status.send(ConnectionState::Closed("Disconnected".into()));
+                        break;
+                    }
+                }
+                Disconnect::Failed(reason) => {
+                    self.status.send(ConnectionState::Closed(reason));
+                    break;
+                }
+                Disconnect::Shutdown => {
+                    self.status.send(ConnectionState::Closed("Disconnected".into()));
+                    break;
+                }
             }
-        });
+        }
+        log::debug!("WebSocket closed!");
+    }
+
+    /// Runs one connection until it drops. `attempt` is reset once the socket is open.
+    async fn connect(&mut self) -> Disconnect {
+        // Resolved per attempt so a new address from the settings screen applies on reconnect.
+        let endpoint = endpoint::resolve();
+        log::debug!("Connecting to {} ({})", endpoint.url, endpoint.source.describe());
+        let mut ws = match WebSocket::open(&endpoint.url) {
+            Ok(ws) => ws,
+            Err(e) => {
+                log::error!("ws: {:?}", e);
+                return Disconnect::Failed(e.to_string());
+            }
+        };
+
+        // `send` waits for the handshake, so afterwards the state tells us whether it worked.
+        let register = self.register.borrow().clone();
+        if ws.send(Message::Text(register)).await.is_err() || !is_open(&ws) {
+            return Disconnect::Lost;
+        }
+        self.attempt = 0;
+        self.status.send(ConnectionState::Open);
+        log::debug!("WebSocket open");
 
-        spawn_local(async move {
-            while let Some(msg) = read.next().await {
-                match msg {
+        // The server forgets uploads with the connection, so a leftover chunk is useless.
+        if let Some(s) = self.pending.take().filter(|m| matches!(m, Message::Text(_))) {
+            if let Err(e) = send(&mut ws, s, &mut self.pending).await {
+                log::error!("ws: {:?}", e);
+                return Disconnect::Lost;
+            }
+        }
+
+        loop {
+            match select(ws.next(), self.in_rx.next()).await {
+                Either::Left((Some(msg), _)) => match msg {
                     Ok(Message::Text(data)) => {
                         log::debug!("From websocket: {}", data);
-                        event_bus.send(Request::EventBusMsg(data));
+                        self.event_bus.send(Request::EventBusMsg(data));
                     }
                     Ok(Message::Bytes(b)) => {
                         let decoded = std::str::from_utf8(&b);
                         if let Ok(val) = decoded {
                             log::debug!("From websocket: {}", val);
-                            event_bus.send(Request::EventBusMsg(val.into()));
+                            self.event_bus.send(Request::EventBusMsg(val.into()));
                         }
                     }
                     Err(e) => {
                         log::error!("ws: {:?}", e);
                     }
+                },
+                Either::Left((None, _)) => return Disconnect::Lost,
+                Either::Right((Some(s), _)) => {
+                    match &s {
+                        Message::Text(text) => log::debug!("Got event from channel! {}", text),
+                        Message::Bytes(bytes) => log::debug!("Got {} bytes from channel", bytes.len()),
+                    }
+                    if let Err(e) = send(&mut ws, s, &mut self.pending).await {
+                        log::error!("ws: {:?}", e);
+                        return Disconnect::Lost;
+                    }
                 }
+                Either::Right((None, _)) => return Disconnect::Shutdown,
             }
-            log::debug!("WebSocket closed!");
-        });
+        }
+    }
+}
 
-        Self { tx: in_tx }
+/// Sends `s`, parking it in `pending` if the socket turns out to be gone.
+async fn send(ws: &mut WebSocket, s: Message, pending: &mut Option<Message>) -> Result<(), String> {
+    // Browsers silently drop frames sent on a closing socket, so check before and after.
+    if !is_open(ws) {
+        *pending = Some(s);
+        return Err("socket not open".into());
     }
-}
\ No newline at end of file
+    if let Err(e) = ws.send(s.clone()).await {
+        *pending = Some(s);
+        return Err(e.to_string());
+    }
+    Ok(())
+}
+
+fn is_open(ws: &WebSocket) -> bool {
+    matches!(ws.state(), State::Open)
+}
+
+/// Exponential backoff with jitter, so a restarted server isn't hit by every client at once.
+fn backoff_delay(attempt: u32) -> u32 {
+    let ceiling = BACKOFF_BASE_MS
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(BACKOFF_MAX_MS);
+    let jitter = 0.5 + js_sys::Math::random() / 2.0;
+    (ceiling as f64 * jitter) as u32
+}